@@ -0,0 +1,26 @@
+use std::process::Command;
+
+// Records the compiler version and git commit the benchmarks were built from,
+// so result files can say where their numbers came from.
+fn main() {
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    if let Some(version) = output(&rustc, &["--version"]) {
+        println!("cargo:rustc-env=MAP_BENCH_RUSTC={version}");
+    }
+    if let Some(commit) = output("git", &["rev-parse", "HEAD"]) {
+        println!("cargo:rustc-env=MAP_BENCH_GIT_COMMIT={commit}");
+    }
+
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+}
+
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8(output.stdout).ok()?;
+    Some(text.trim().to_string()).filter(|t| !t.is_empty())
+}
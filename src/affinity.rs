@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+
+use crate::perf_mem::{CoreId, CoreInfo};
+
+/// Largest logical processor index a `CpuSet` can hold. Matches the size of
+/// the Linux `cpu_set_t` and 16 Windows processor groups.
+pub const MAX_CPUS: usize = 1024;
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// A fixed size set of logical processors.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct CpuSet {
+    bits: [u64; MAX_CPUS / WORD_BITS],
+}
+
+impl Default for CpuSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for CpuSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl FromIterator<usize> for CpuSet {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        let mut set = Self::new();
+        for cpu in iter {
+            set.insert(cpu);
+        }
+        set
+    }
+}
+
+impl CpuSet {
+    pub const fn new() -> Self {
+        Self {
+            bits: [0; MAX_CPUS / WORD_BITS],
+        }
+    }
+
+    pub fn insert(&mut self, cpu: usize) {
+        assert!(cpu < MAX_CPUS, "cpu {cpu} out of range");
+        self.bits[cpu / WORD_BITS] |= 1 << (cpu % WORD_BITS);
+    }
+
+    pub fn contains(&self, cpu: usize) -> bool {
+        cpu < MAX_CPUS && self.bits[cpu / WORD_BITS] & (1 << (cpu % WORD_BITS)) != 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..MAX_CPUS).filter(move |&cpu| self.contains(cpu))
+    }
+
+    /// The cpus of one Windows processor group, from its 64 bit mask.
+    pub fn from_group_mask(processor_group: u16, mask: usize) -> Self {
+        let mut set = Self::new();
+        set.bits[processor_group as usize] = mask as u64;
+        set
+    }
+
+    /// The 64 bit mask for one Windows processor group.
+    #[cfg(target_os = "windows")]
+    pub fn group_mask(&self, processor_group: u16) -> usize {
+        self.bits[processor_group as usize] as usize
+    }
+}
+
+pub fn core_cpus(core: &CoreId) -> CpuSet {
+    CpuSet::from_iter([core.cpu_index()])
+}
+
+// A node with more than 64 cpus spans processor groups, and like Windows
+// affinity this only covers the part in the core's own group.
+pub fn numa_node_cpus(core: &CoreId) -> CpuSet {
+    CpuSet::from_group_mask(core.processor_group, core.numa_mask)
+}
+
+pub fn pin_to_core(core: &CoreId) -> Result<(), String> {
+    set_thread_affinity(&core_cpus(core))
+}
+
+pub fn pin_to_numa_node(core: &CoreId) -> Result<(), String> {
+    set_thread_affinity(&numa_node_cpus(core))
+}
+
+/// How benchmark threads are placed on the machine.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pinning {
+    #[default]
+    None,
+    Core,
+    #[serde(rename = "numa")]
+    NumaNode,
+}
+
+impl std::str::FromStr for Pinning {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Pinning::None),
+            "core" => Ok(Pinning::Core),
+            "numa" => Ok(Pinning::NumaNode),
+            _ => Err(format!("unknown pinning '{s}', expected none, core or numa")),
+        }
+    }
+}
+
+impl Pinning {
+    /// The cpus a given benchmark thread should run on. Threads are spread
+    /// over the cores in `CoreInfo::ids` order, which alternates numa nodes.
+    pub fn cpu_set(&self, core_info: &CoreInfo, thread_index: usize) -> Option<CpuSet> {
+        let core = &core_info.ids[thread_index % core_info.ids.len()];
+        match self {
+            Pinning::None => None,
+            Pinning::Core => Some(core_cpus(core)),
+            Pinning::NumaNode => Some(numa_node_cpus(core)),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn set_thread_affinity(cpus: &CpuSet) -> Result<(), String> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        for cpu in cpus.iter() {
+            libc::CPU_SET(cpu, &mut set);
+        }
+
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(format!(
+                "Failed to set thread affinity for cpus {:?}: {}",
+                cpus,
+                std::io::Error::last_os_error()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// A Windows thread can only have affinity within one processor group, so the
+// group of the lowest cpu in the set is used.
+#[cfg(target_os = "windows")]
+pub fn set_thread_affinity(cpus: &CpuSet) -> Result<(), String> {
+    use winapi::um::processthreadsapi::GetCurrentThread;
+    use winapi::um::processtopologyapi::SetThreadGroupAffinity;
+    use winapi::um::winnt::{GROUP_AFFINITY, HANDLE};
+
+    let Some(first) = cpus.iter().next() else {
+        return Err("Cannot set thread affinity to an empty cpu set".to_string());
+    };
+
+    let current_thread: HANDLE = unsafe { GetCurrentThread() };
+    if current_thread.is_null() {
+        return Err("Failed to get current thread handle".to_string());
+    }
+
+    let processor_group = (first / WORD_BITS) as u16;
+    let mask = cpus.group_mask(processor_group);
+
+    let mut group_mask: GROUP_AFFINITY = unsafe { std::mem::zeroed() };
+    group_mask.Mask = mask;
+    group_mask.Group = processor_group;
+
+    let result =
+        unsafe { SetThreadGroupAffinity(current_thread, &group_mask, std::ptr::null_mut()) };
+
+    if result == 0 {
+        return Err(format!(
+            "Failed to set thread affinity for core {:X}:{:X}: {}",
+            mask,
+            processor_group,
+            crate::perf_info::get_last_error_message(),
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,203 @@
+// The global allocator is picked at build time with one of the `alloc-*`
+// cargo features, and is always wrapped in a `CountingAlloc` so the memory
+// footprint of the maps can be measured whichever allocator is in use.
+
+use serde::{Deserialize, Serialize};
+use std::alloc::{GlobalAlloc, Layout};
+use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering};
+
+#[cfg(any(
+    all(feature = "alloc-system", feature = "alloc-mimalloc"),
+    all(feature = "alloc-system", feature = "alloc-numa"),
+    all(feature = "alloc-mimalloc", feature = "alloc-numa"),
+))]
+compile_error!("only one of the alloc-system, alloc-mimalloc and alloc-numa features can be enabled");
+
+#[cfg(feature = "alloc-mimalloc")]
+#[global_allocator]
+static GLOBAL: CountingAlloc<mimalloc::MiMalloc> = CountingAlloc::new(mimalloc::MiMalloc);
+
+#[cfg(feature = "alloc-numa")]
+#[global_allocator]
+static GLOBAL: CountingAlloc<crate::numa_allocator::NumaAwareAllocator> =
+    CountingAlloc::new(crate::numa_allocator::NumaAwareAllocator::new());
+
+#[cfg(not(any(feature = "alloc-mimalloc", feature = "alloc-numa")))]
+#[global_allocator]
+static GLOBAL: CountingAlloc<std::alloc::System> = CountingAlloc::new(std::alloc::System);
+
+/// Name of the global allocator this binary was built with.
+pub const NAME: &str = if cfg!(feature = "alloc-mimalloc") {
+    "mimalloc"
+} else if cfg!(feature = "alloc-numa") {
+    "numa"
+} else {
+    "system"
+};
+
+/// Passes every call on to another allocator, keeping count while counting is
+/// switched on. When it is off the only cost is one relaxed load.
+pub struct CountingAlloc<A> {
+    inner: A,
+    counting: AtomicBool,
+    bytes: AtomicIsize,
+    peak_bytes: AtomicIsize,
+    allocations: AtomicUsize,
+}
+
+impl<A> CountingAlloc<A> {
+    pub const fn new(inner: A) -> Self {
+        Self {
+            inner,
+            counting: AtomicBool::new(false),
+            bytes: AtomicIsize::new(0),
+            peak_bytes: AtomicIsize::new(0),
+            allocations: AtomicUsize::new(0),
+        }
+    }
+
+    #[inline]
+    fn record(&self, bytes: isize, allocations: usize) {
+        if !self.counting.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let now = self.bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.peak_bytes.fetch_max(now, Ordering::Relaxed);
+        self.allocations.fetch_add(allocations, Ordering::Relaxed);
+    }
+
+    fn start_counting(&self) {
+        self.bytes.store(0, Ordering::Relaxed);
+        self.peak_bytes.store(0, Ordering::Relaxed);
+        self.allocations.store(0, Ordering::Relaxed);
+        self.counting.store(true, Ordering::SeqCst);
+    }
+
+    fn stop_counting(&self) {
+        self.counting.store(false, Ordering::SeqCst);
+    }
+
+    fn stats(&self) -> AllocStats {
+        AllocStats {
+            bytes: self.bytes.load(Ordering::Relaxed),
+            peak_bytes: self.peak_bytes.load(Ordering::Relaxed),
+            allocations: self.allocations.load(Ordering::Relaxed),
+        }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAlloc<A> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            self.record(layout.size() as isize, 1);
+        }
+        ptr
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            self.record(layout.size() as isize, 1);
+        }
+        ptr
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        self.record(-(layout.size() as isize), 0);
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            self.record(new_size as isize - layout.size() as isize, 1);
+        }
+        new_ptr
+    }
+}
+
+/// Heap use since counting started. Memory allocated before then and freed
+/// while counting takes bytes off, so start counting before creating the
+/// structure being measured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct AllocStats {
+    /// Bytes currently allocated.
+    pub bytes: isize,
+    /// Most bytes allocated at once.
+    pub peak_bytes: isize,
+    /// Calls to alloc and realloc.
+    pub allocations: usize,
+}
+
+/// Heap use after a map was prefilled and after the timed run on it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Footprint {
+    pub prefill: usize,
+    pub after_prefill: AllocStats,
+    pub after_run: AllocStats,
+}
+
+impl Footprint {
+    pub fn bytes_per_entry(&self) -> f64 {
+        self.after_prefill.bytes as f64 / self.prefill.max(1) as f64
+    }
+
+    pub fn summary(&self) -> String {
+        let (p, r) = (&self.after_prefill, &self.after_run);
+        format!(
+            "prefill: {} bytes ({:.1}/entry) {} allocs  run: {} bytes  peak: {} bytes  {} allocs",
+            p.bytes,
+            self.bytes_per_entry(),
+            p.allocations,
+            r.bytes,
+            r.peak_bytes,
+            r.allocations
+        )
+    }
+}
+
+/// Resets the counts to zero and starts counting. Only one measurement can
+/// be taken at a time.
+pub fn start_counting() {
+    GLOBAL.start_counting();
+}
+
+pub fn stop_counting() {
+    GLOBAL.stop_counting();
+}
+
+pub fn stats() -> AllocStats {
+    GLOBAL.stats()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A separate allocator rather than the global one, which other tests
+    // allocate through while they run
+    #[test]
+    fn test_counts_only_while_counting() {
+        let counting = CountingAlloc::new(std::alloc::System);
+        let layout = Layout::array::<u64>(1000).unwrap();
+
+        counting.start_counting();
+        let v = unsafe { counting.alloc(layout) };
+        let during = counting.stats();
+        counting.stop_counting();
+        assert_eq!(during, AllocStats { bytes: 8000, peak_bytes: 8000, allocations: 1 });
+
+        let w = unsafe { counting.alloc(layout) };
+        unsafe {
+            counting.dealloc(v, layout);
+            counting.dealloc(w, layout);
+        }
+        assert_eq!(counting.stats(), during);
+    }
+}
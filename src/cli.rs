@@ -0,0 +1,377 @@
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+use crate::affinity::Pinning;
+use crate::perf::TrialConfig;
+use crate::perf_mem;
+use crate::plot::{Chart, Metric};
+use crate::scenario::KeyDistribution;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "map-bench", about = "Concurrent map and memory benchmarks")]
+pub struct Options {
+    /// Directory the charts and machine info are written to
+    #[structopt(long, short, default_value = ".", parse(from_os_str))]
+    pub out_dir: PathBuf,
+
+    /// Directory of external series in CSV or JSON, overlaid on the charts
+    /// of matching workloads
+    #[structopt(long, default_value = "series", parse(from_os_str))]
+    pub series_dir: PathBuf,
+
+    /// Color series by the first word of their name, so e.g. "scc u64" and
+    /// "scc str" are shades of one hue
+    #[structopt(long)]
+    pub color_by_family: bool,
+
+    /// Suite to run, all of them if omitted
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Shared map latency by thread count, per read mix and prefill size
+    MapOp(SharedMapArgs),
+    /// Shared map latency with u64 vs String keys
+    MapKey(SharedMapArgs),
+    /// Single threaded map latency by prefill size
+    Map(MapArgs),
+    /// Chains of dependent reads over growing blocks
+    MemIndirect,
+    /// Atomic and mutex counters, shared and per numa node
+    FetchAdd(ThreadArgs),
+    /// Contention on one or more heaps
+    HeapAlloc(ThreadArgs),
+    /// Independent reads and writes to local and remote numa memory
+    MemRw(ThreadArgs),
+    /// Heap use of each map by item count
+    Footprint(FootprintArgs),
+    /// Shared map scenarios described in TOML or JSON files
+    Scenario(ScenarioArgs),
+    /// Compare two result files, or two directories of them, and fail if
+    /// any point regressed
+    Compare(CompareArgs),
+    /// Build report.html from the charts and result files in the output
+    /// directory
+    Report,
+    /// List the shared maps that can be benchmarked
+    Maps,
+    /// Only write the processor info
+    CpuInfo,
+    /// Every suite with default settings
+    All,
+}
+
+impl Command {
+    /// Whether the command measures this machine, so its results should
+    /// have the processor info next to them.
+    pub fn writes_cpu_info(&self) -> bool {
+        !matches!(self, Command::Compare(_) | Command::Report | Command::Maps)
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ScenarioArgs {
+    /// Scenario files to run
+    #[structopt(required = true, parse(from_os_str))]
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct CompareArgs {
+    /// Results of the baseline run, a .json result file or a directory of them
+    #[structopt(parse(from_os_str))]
+    pub baseline: PathBuf,
+
+    /// Results to check against the baseline, matched by file name
+    #[structopt(parse(from_os_str))]
+    pub candidate: PathBuf,
+
+    /// Percentage a point can get worse by before it counts as a regression
+    #[structopt(long, default_value = "5")]
+    pub threshold: f64,
+
+    /// Metric to compare
+    #[structopt(long, default_value = "latency")]
+    pub metric: Metric,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ThreadArgs {
+    /// Thread counts to run, from 1 up to the number of cpus if omitted
+    #[structopt(long, short, use_delimiter = true)]
+    pub threads: Vec<usize>,
+
+    /// Metrics to chart: latency, p50, p90, p99, p999, max, wall-time,
+    /// throughput, thread-throughput-min, thread-throughput-mean,
+    /// thread-throughput-max, or the per op hardware counts cycles,
+    /// instructions, l1d-misses, llc-misses, dtlb-misses and remote-accesses
+    #[structopt(long, use_delimiter = true, default_value = "latency")]
+    pub metrics: Vec<Metric>,
+
+    /// Charts to draw of each metric: lines, bars, heatmap or speedup
+    #[structopt(long, use_delimiter = true, default_value = "lines")]
+    pub charts: Vec<Chart>,
+}
+
+// The Default impls match the structopt defaults, see test_defaults_match_cli
+impl Default for ThreadArgs {
+    fn default() -> Self {
+        Self {
+            threads: Vec::new(),
+            metrics: vec![Metric::Latency],
+            charts: vec![Chart::Lines],
+        }
+    }
+}
+
+impl ThreadArgs {
+    pub fn thread_counts(&self) -> Vec<usize> {
+        if self.threads.is_empty() {
+            (1..=perf_mem::get_num_cpus()).collect()
+        } else {
+            self.threads.clone()
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct SharedMapArgs {
+    /// Operations per run, split over the threads
+    #[structopt(long, default_value = "40000000")]
+    pub total_ops: usize,
+
+    /// Items inserted before timing starts
+    #[structopt(long, use_delimiter = true)]
+    pub prefill: Vec<usize>,
+
+    /// Percentage of reads, the rest are inserts
+    #[structopt(long, use_delimiter = true, parse(try_from_str = parse_percent))]
+    pub read: Vec<usize>,
+
+    #[structopt(flatten)]
+    pub threads: ThreadArgs,
+
+    /// Maps to run, see the maps command for the names
+    #[structopt(long, use_delimiter = true)]
+    pub maps: Vec<String>,
+
+    /// Thread placement: none, core or numa
+    #[structopt(long, default_value = "none")]
+    pub pinning: Pinning,
+
+    /// How keys are picked: uniform, zipf[:theta], hotspot:ops%:keys%, latest
+    /// or sequential
+    #[structopt(long, default_value = "uniform")]
+    pub keys: KeyDistribution,
+
+    /// Time one operation in this many for the latency percentiles, 1 for all
+    #[structopt(long, default_value = "16")]
+    pub sample_every: usize,
+
+    /// Untimed runs before each point, to warm caches and the heap
+    #[structopt(long, default_value = "0")]
+    pub warmup: usize,
+
+    /// Runs per point, charted as the mean with a 95% confidence band
+    #[structopt(long, default_value = "1")]
+    pub trials: usize,
+
+    /// Leave out trials more than 1.5 interquartile ranges from the others
+    #[structopt(long)]
+    pub reject_outliers: bool,
+
+    /// Sample progress every this many milliseconds and chart throughput over time
+    #[structopt(long)]
+    pub timeline: Option<u64>,
+
+    /// Run open loop at these total operations per second and chart latency
+    /// against achieved throughput
+    #[structopt(long, use_delimiter = true)]
+    pub rates: Vec<f64>,
+
+    /// Check every operation's outcome and the map's contents after each run,
+    /// failing if a map lost or invented keys
+    #[structopt(long)]
+    pub verify: bool,
+}
+
+impl Default for SharedMapArgs {
+    fn default() -> Self {
+        Self {
+            total_ops: 40_000_000,
+            prefill: Vec::new(),
+            read: Vec::new(),
+            threads: ThreadArgs::default(),
+            maps: Vec::new(),
+            pinning: Pinning::None,
+            keys: KeyDistribution::Uniform,
+            sample_every: 16,
+            warmup: 0,
+            trials: 1,
+            reject_outliers: false,
+            timeline: None,
+            rates: Vec::new(),
+            verify: false,
+        }
+    }
+}
+
+impl SharedMapArgs {
+    /// The (read %, prefill) pairs to chart. With neither flag given each
+    /// suite keeps its usual set, otherwise every combination is run, taking
+    /// the values of a missing flag from the defaults.
+    pub fn runs(&self, defaults: &[(usize, usize)]) -> Vec<(usize, usize)> {
+        if self.read.is_empty() && self.prefill.is_empty() {
+            return defaults.to_vec();
+        }
+
+        let reads = or_defaults(&self.read, defaults.iter().map(|d| d.0));
+        let prefills = or_defaults(&self.prefill, defaults.iter().map(|d| d.1));
+
+        reads
+            .iter()
+            .flat_map(|&read| prefills.iter().map(move |&prefill| (read, prefill)))
+            .collect()
+    }
+
+    pub fn map_names(&self, defaults: &[&str]) -> Vec<String> {
+        or_defaults(&self.maps, defaults.iter().map(|s| s.to_string()))
+    }
+
+    pub fn trial_config(&self) -> TrialConfig {
+        TrialConfig {
+            warmup: self.warmup,
+            trials: self.trials,
+            reject_outliers: self.reject_outliers,
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct MapArgs {
+    /// Operations per map and prefill size
+    #[structopt(long, default_value = "10000000")]
+    pub total_ops: usize,
+
+    /// Prefill sizes, 5k to 100k in steps of 5k if omitted
+    #[structopt(long, use_delimiter = true)]
+    pub prefill: Vec<usize>,
+
+    /// Maps to run: std, hb, bfix, sfix
+    #[structopt(long, use_delimiter = true)]
+    pub maps: Vec<String>,
+}
+
+impl Default for MapArgs {
+    fn default() -> Self {
+        Self {
+            total_ops: 10_000_000,
+            prefill: Vec::new(),
+            maps: Vec::new(),
+        }
+    }
+}
+
+impl MapArgs {
+    pub fn prefills(&self) -> Vec<usize> {
+        or_defaults(&self.prefill, (1..=20).map(|i| i * 5000))
+    }
+
+    pub fn map_names(&self) -> Vec<String> {
+        or_defaults(&self.maps, ["std", "hb", "bfix", "sfix"].map(String::from))
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct FootprintArgs {
+    /// Item counts, 10k to 1m if omitted
+    #[structopt(long, use_delimiter = true)]
+    pub prefill: Vec<usize>,
+
+    /// Operations on one thread after the prefill
+    #[structopt(long, default_value = "1000000")]
+    pub total_ops: usize,
+
+    /// Percentage of reads in those operations, the rest are inserts
+    #[structopt(long, default_value = "99", parse(try_from_str = parse_percent))]
+    pub read: usize,
+
+    /// Maps to measure: the shared maps from the maps command, or the
+    /// non-shared std, hb and sfix
+    #[structopt(long, use_delimiter = true)]
+    pub maps: Vec<String>,
+}
+
+impl Default for FootprintArgs {
+    fn default() -> Self {
+        Self {
+            prefill: Vec::new(),
+            total_ops: 1_000_000,
+            read: 99,
+            maps: Vec::new(),
+        }
+    }
+}
+
+impl FootprintArgs {
+    pub fn prefills(&self) -> Vec<usize> {
+        or_defaults(&self.prefill, [10_000, 50_000, 100_000, 250_000, 500_000, 1_000_000])
+    }
+
+    pub fn map_names(&self) -> Vec<String> {
+        or_defaults(&self.maps, ["scc", "bfix", "std-rwlock", "sfix"].map(String::from))
+    }
+}
+
+fn or_defaults<T: Clone + PartialEq>(values: &[T], defaults: impl IntoIterator<Item = T>) -> Vec<T> {
+    if !values.is_empty() {
+        return values.to_vec();
+    }
+
+    let mut list = Vec::new();
+    for d in defaults {
+        if !list.contains(&d) {
+            list.push(d);
+        }
+    }
+    list
+}
+
+fn parse_percent(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(n) if n <= 100 => Ok(n),
+        _ => Err(format!("'{s}' is not a percentage")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_match_cli() {
+        let args = |name: &str| vec!["map-bench".to_string(), name.to_string()];
+        let same = |parsed: &dyn std::fmt::Debug, default: &dyn std::fmt::Debug| {
+            assert_eq!(format!("{parsed:?}"), format!("{default:?}"));
+        };
+
+        match Command::from_iter(args("fetch-add")) {
+            Command::FetchAdd(parsed) => same(&parsed, &ThreadArgs::default()),
+            c => panic!("unexpected {c:?}"),
+        }
+        match Command::from_iter(args("map-op")) {
+            Command::MapOp(parsed) => same(&parsed, &SharedMapArgs::default()),
+            c => panic!("unexpected {c:?}"),
+        }
+        match Command::from_iter(args("map")) {
+            Command::Map(parsed) => same(&parsed, &MapArgs::default()),
+            c => panic!("unexpected {c:?}"),
+        }
+        match Command::from_iter(args("footprint")) {
+            Command::Footprint(parsed) => same(&parsed, &FootprintArgs::default()),
+            c => panic!("unexpected {c:?}"),
+        }
+    }
+}
@@ -0,0 +1,330 @@
+// Compares the result files of two runs point by point, so a change to a map
+// can be checked against a baseline without eyeballing charts.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::cli::CompareArgs;
+use crate::plot::{write_diff_plot, Metric};
+use crate::results::{Record, Results};
+use crate::scenario::KeyType;
+use crate::stats;
+
+/// One point found in both the baseline and the candidate.
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub series: String,
+    pub thread_count: u64,
+    /// The map size, for suites sized by it.
+    pub items: Option<u64>,
+    /// The scheduled rate, for open loop runs.
+    pub target_rate: Option<f64>,
+    pub baseline: f64,
+    pub candidate: f64,
+    /// Percentage change of the value from the baseline.
+    pub percent: f64,
+    /// Whether the difference is significant given the variance of the
+    /// trials, `None` if either side was measured only once.
+    pub significant: Option<bool>,
+}
+
+impl Change {
+    /// The series, with the scheduled rate for open loop points.
+    pub fn label(&self) -> String {
+        match self.target_rate {
+            Some(rate) => format!("{} @ {} ops/s", self.series, rate),
+            None => self.series.clone(),
+        }
+    }
+
+    /// Where the point goes along the x axis, as in the charts of the run.
+    pub fn x(&self) -> u64 {
+        self.items.unwrap_or(self.thread_count)
+    }
+
+    /// Percentage by which the candidate is worse, negative if it is better.
+    pub fn worse_by(&self, metric: Metric) -> f64 {
+        if metric.higher_is_better() {
+            -self.percent
+        } else {
+            self.percent
+        }
+    }
+
+    /// Worse by more than the threshold, unless the trials show the
+    /// difference could be noise.
+    pub fn regressed(&self, metric: Metric, threshold: f64) -> bool {
+        self.worse_by(metric) > threshold && self.significant != Some(false)
+    }
+
+    pub fn improved(&self, metric: Metric, threshold: f64) -> bool {
+        self.worse_by(metric) < -threshold && self.significant != Some(false)
+    }
+}
+
+type PointKey = (String, Option<KeyType>, u64, Option<u64>, Option<u64>);
+
+/// Points are matched by map and key type where the results say which map
+/// they measured, otherwise by series name, and by thread count, item count
+/// and scheduled rate.
+fn point_key(record: &Record) -> PointKey {
+    let series = record.adapter.clone().unwrap_or_else(|| record.name.clone());
+    let rate = record.target_rate.map(f64::to_bits);
+    (series, record.key_type, record.thread_count, record.items, rate)
+}
+
+/// The records of a result set by point, failing if two share a point as
+/// one would otherwise be compared against the wrong run.
+fn points(results: &Results) -> Result<HashMap<PointKey, &Record>, String> {
+    let mut points = HashMap::new();
+    for record in &results.records {
+        if points.insert(point_key(record), record).is_some() {
+            let items = record.items.map(|n| format!(", {} items", n)).unwrap_or_default();
+            let rate = record.target_rate.map(|r| format!(", {} ops/s", r)).unwrap_or_default();
+            return Err(format!(
+                "{} has more than one point at {} threads{}{}",
+                record.name, record.thread_count, items, rate
+            ));
+        }
+    }
+    Ok(points)
+}
+
+/// Trials kept for a point, 1 if it was measured once.
+fn trial_count(record: &Record) -> usize {
+    record.spread.map_or(1, |s| s.trials - s.rejected)
+}
+
+/// The change of every point in both result sets that has the metric.
+pub fn compare(baseline: &Results, candidate: &Results, metric: Metric) -> Result<Vec<Change>, String> {
+    points(baseline)?;
+    let candidates = points(candidate)?;
+
+    let mut changes = Vec::new();
+    for base in &baseline.records {
+        let Some(cand) = candidates.get(&point_key(base)) else {
+            continue;
+        };
+        let (b, c) = (base.measurement(), cand.measurement());
+        let (Some(base_value), Some(cand_value)) = (metric.value(&b), metric.value(&c)) else {
+            continue;
+        };
+
+        let significant = match (metric.summary(&b), metric.summary(&c)) {
+            (Some(bs), Some(cs)) => stats::means_differ(&bs, trial_count(base), &cs, trial_count(cand)),
+            _ => None,
+        };
+        changes.push(Change {
+            series: base.name.clone(),
+            thread_count: base.thread_count,
+            items: base.items,
+            target_rate: base.target_rate,
+            baseline: base_value,
+            candidate: cand_value,
+            percent: (cand_value - base_value) / base_value.abs().max(f64::MIN_POSITIVE) * 100.0,
+            significant,
+        });
+    }
+    Ok(changes)
+}
+
+/// Pairs up the result files to compare, by file name when given two
+/// directories. Each pair is one workload.
+fn result_pairs(baseline: &Path, candidate: &Path) -> Result<Vec<(String, Results, Results)>, String> {
+    if baseline.is_file() && candidate.is_file() {
+        let workload = baseline.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        return Ok(vec![(workload, Results::load(baseline)?, Results::load(candidate)?)]);
+    }
+    if !(baseline.is_dir() && candidate.is_dir()) {
+        return Err("compare takes two result files or two directories of them".to_string());
+    }
+
+    let entries = std::fs::read_dir(baseline).map_err(|e| format!("Failed to read {}: {}", baseline.display(), e))?;
+    let mut files: Vec<_> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .collect();
+    files.sort();
+
+    let mut pairs = Vec::new();
+    for base_path in files {
+        // Other json, such as the machine info, is left out
+        let Ok(base) = Results::load(&base_path) else {
+            continue;
+        };
+        let cand_path = candidate.join(base_path.file_name().unwrap());
+        if !cand_path.is_file() {
+            continue;
+        }
+        match Results::load(&cand_path) {
+            Ok(cand) => {
+                let workload = base_path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+                pairs.push((workload, base, cand));
+            }
+            Err(e) => println!("Skipping {}", e),
+        }
+    }
+    Ok(pairs)
+}
+
+/// Top level settings that differ between the runs, which may explain a
+/// change as well as the code can.
+fn changed_settings(baseline: &Results, candidate: &Results) -> Vec<String> {
+    let mut changed = Vec::new();
+    if let (Some(b), Some(c)) = (baseline.config.as_object(), candidate.config.as_object()) {
+        for (key, value) in b {
+            if c.get(key) != Some(value) {
+                changed.push(key.clone());
+            }
+        }
+    }
+    if baseline.info.allocator != candidate.info.allocator {
+        changed.push("allocator".to_string());
+    }
+    let cpu = |r: &Results| r.info.machine.as_ref().map(|m| m.cpu_model.clone());
+    if cpu(baseline) != cpu(candidate) {
+        changed.push("cpu".to_string());
+    }
+    changed
+}
+
+pub fn run_compare(args: &CompareArgs, out_dir: &Path) -> Result<(), String> {
+    let metric = args.metric;
+    let pairs = result_pairs(&args.baseline, &args.candidate)?;
+    if pairs.is_empty() {
+        return Err("no result files in common to compare".to_string());
+    }
+
+    let mut regressions = 0;
+    for (workload, baseline, candidate) in &pairs {
+        println!("{} ({})", workload, metric.label());
+        let changed = changed_settings(baseline, candidate);
+        if !changed.is_empty() {
+            println!("  settings differ: {}", changed.join(", "));
+        }
+
+        let changes = compare(baseline, candidate, metric).map_err(|e| format!("Failed to compare {}: {}", workload, e))?;
+        if changes.is_empty() {
+            println!("  no points in common");
+            continue;
+        }
+
+        println!(
+            "  {:32} {:>7} {:>10} {:>12} {:>12} {:>8}",
+            "series", "threads", "items", "baseline", "candidate", "change"
+        );
+        for change in &changes {
+            let verdict = if change.regressed(metric, args.threshold) {
+                regressions += 1;
+                "REGRESSION"
+            } else if change.improved(metric, args.threshold) {
+                "improved"
+            } else {
+                ""
+            };
+            let significance = match change.significant {
+                Some(true) => "",
+                Some(false) => " (within noise)",
+                None => " (single trial)",
+            };
+            println!(
+                "  {:32} {:>7} {:>10} {:>12} {:>12} {:>+7.1}%  {}{}",
+                change.label(),
+                change.thread_count,
+                change.items.map(|n| n.to_string()).unwrap_or_default(),
+                metric.format(change.baseline),
+                metric.format(change.candidate),
+                change.percent,
+                verdict,
+                significance
+            );
+        }
+
+        let mut series: BTreeMap<String, Vec<(f64, f64)>> = BTreeMap::new();
+        for change in &changes {
+            series
+                .entry(change.label())
+                .or_default()
+                .push((change.x() as f64, change.percent));
+        }
+        let path = out_dir.join(format!("compare-{}-{}.svg", workload, metric.name()));
+        let caption = format!("{} change from baseline ({})", metric.label(), workload);
+        let series = series.iter().map(|(label, points)| (label.as_str(), points.clone())).collect();
+        write_diff_plot(series, &caption, args.threshold, &path)
+            .map_err(|e| format!("Failed to plot {}: {}", path.display(), e))?;
+    }
+
+    if regressions > 0 {
+        return Err(format!(
+            "{} points regressed by more than {}%",
+            regressions, args.threshold
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::perf::{Measurement, Spread};
+    use crate::stats::Summary;
+
+    fn results(latencies: &[(&'static str, u64, &[f64])]) -> Results {
+        let measurements: Vec<Measurement> = latencies
+            .iter()
+            .map(|&(name, thread_count, trials)| Measurement {
+                spread: Some(Spread {
+                    trials: trials.len(),
+                    rejected: 0,
+                    latency: Summary::new(trials),
+                    ops_per_sec: None,
+                }),
+                ..Measurement::new(name, stats::mean(trials), thread_count)
+            })
+            .collect();
+        Results::new(&serde_json::json!({}), &measurements)
+            .mark_maps(|name| Some((name.to_string(), KeyType::U64)))
+    }
+
+    #[test]
+    fn test_compare_matches_points_and_flags_regressions() {
+        let baseline = results(&[
+            ("scc", 1, &[100.0, 101.0, 99.0, 100.5]),
+            ("bfix", 1, &[50.0, 50.5, 49.5, 50.0]),
+            ("std-rwlock", 1, &[80.0, 81.0, 79.0, 80.0]),
+        ]);
+        let candidate = results(&[
+            ("scc", 1, &[120.0, 121.0, 119.0, 120.5]),
+            ("bfix", 1, &[50.2, 50.4, 49.6, 50.1]),
+            ("bfix", 2, &[30.0, 30.0, 30.0, 30.0]),
+        ]);
+
+        let changes = compare(&baseline, &candidate, Metric::Latency).unwrap();
+        assert_eq!(changes.len(), 2);
+
+        let scc = changes.iter().find(|c| c.series == "scc").unwrap();
+        assert!((scc.percent - 20.0).abs() < 0.5);
+        assert_eq!(scc.significant, Some(true));
+        assert!(scc.regressed(Metric::Latency, 5.0));
+
+        let bfix = changes.iter().find(|c| c.series == "bfix").unwrap();
+        assert_eq!(bfix.significant, Some(false));
+        assert!(!bfix.regressed(Metric::Latency, 0.0));
+    }
+
+    #[test]
+    fn test_compare_keeps_rates_apart() {
+        let at_rates = |latencies: [f64; 2]| {
+            let mut results = results(&[("scc", 4, &[latencies[0]]), ("scc", 4, &[latencies[1]])]);
+            results.records[0].target_rate = Some(1e5);
+            results.records[1].target_rate = Some(1e6);
+            results
+        };
+        let changes = compare(&at_rates([100.0, 200.0]), &at_rates([100.0, 300.0]), Metric::Latency).unwrap();
+        let percents: Vec<f64> = changes.iter().map(|c| c.percent.round()).collect();
+        assert_eq!(percents, [0.0, 50.0]);
+
+        let duplicated = results(&[("scc", 4, &[100.0]), ("scc", 4, &[200.0])]);
+        assert!(compare(&duplicated, &at_rates([100.0, 200.0]), Metric::Latency).is_err());
+    }
+}
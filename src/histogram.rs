@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+// Each power of two is split into 32 linear buckets, so a recorded value is
+// reported to within about 3%.
+const SUB_BUCKET_BITS: u32 = 5;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+const BUCKETS: usize = (64 - SUB_BUCKET_BITS as usize + 1) * SUB_BUCKETS;
+
+/// Latency percentiles in nanoseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub p999: f64,
+    pub max: f64,
+}
+
+/// A log-linear histogram of nanosecond latencies. Each thread records into
+/// its own and they are merged once the run is over.
+#[derive(Clone)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    sum: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self {
+            counts: vec![0; BUCKETS],
+            total: 0,
+            sum: 0,
+            max: 0,
+        }
+    }
+
+    #[inline]
+    pub fn record(&mut self, value: u64) {
+        self.counts[bucket_index(value)] += 1;
+        self.total += 1;
+        self.sum += value;
+        self.max = self.max.max(value);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (a, b) in self.counts.iter_mut().zip(&other.counts) {
+            *a += b;
+        }
+        self.total += other.total;
+        self.sum += other.sum;
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.sum as f64 / self.total.max(1) as f64
+    }
+
+    /// The value below which `quantile` (0 to 1) of the recorded values fall.
+    pub fn value_at_quantile(&self, quantile: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+
+        let rank = ((quantile * self.total as f64).ceil() as u64).clamp(1, self.total);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return bucket_mid(index).min(self.max);
+            }
+        }
+
+        self.max
+    }
+
+    pub fn percentiles(&self) -> Percentiles {
+        Percentiles {
+            p50: self.value_at_quantile(0.5) as f64,
+            p90: self.value_at_quantile(0.9) as f64,
+            p99: self.value_at_quantile(0.99) as f64,
+            p999: self.value_at_quantile(0.999) as f64,
+            max: self.max as f64,
+        }
+    }
+}
+
+#[inline]
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+
+    let exponent = 63 - value.leading_zeros();
+    let shift = exponent - SUB_BUCKET_BITS;
+    let sub_bucket = (value >> shift) as usize - SUB_BUCKETS;
+    (shift as usize + 1) * SUB_BUCKETS + sub_bucket
+}
+
+fn bucket_low(index: usize) -> u64 {
+    let row = index / SUB_BUCKETS;
+    let sub_bucket = (index % SUB_BUCKETS) as u64;
+    if row == 0 {
+        sub_bucket
+    } else {
+        (SUB_BUCKETS as u64 + sub_bucket) << (row - 1)
+    }
+}
+
+fn bucket_mid(index: usize) -> u64 {
+    let row = index / SUB_BUCKETS;
+    let width = if row == 0 { 1 } else { 1u64 << (row - 1) };
+    bucket_low(index) + width / 2
+}
+
+/// The smallest time two back to back `Instant::now` calls report, which is
+/// taken off every timed operation.
+pub fn timer_overhead() -> u64 {
+    (0..1000)
+        .map(|_| {
+            let start = Instant::now();
+            start.elapsed().as_nanos() as u64
+        })
+        .min()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_bounds() {
+        for value in [0, 1, 31, 32, 33, 63, 64, 65, 1000, 123_456, u64::MAX / 3, u64::MAX] {
+            let index = bucket_index(value);
+            assert!(index < BUCKETS);
+            assert!(bucket_low(index) <= value, "{value}");
+            if index + 1 < BUCKETS {
+                assert!(bucket_low(index + 1) > value, "{value}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_percentiles_within_precision() {
+        let mut a = Histogram::new();
+        let mut b = Histogram::new();
+        for value in 1..=100_000u64 {
+            if value % 2 == 0 { a.record(value) } else { b.record(value) }
+        }
+        a.merge(&b);
+
+        assert_eq!(a.total, 100_000);
+        assert_eq!(a.max, 100_000);
+        assert_eq!(a.mean(), 50_000.5);
+
+        let p = a.percentiles();
+        for (actual, expected) in [(p.p50, 50_000.0), (p.p90, 90_000.0), (p.p99, 99_000.0), (p.p999, 99_900.0)] {
+            assert!((actual - expected).abs() / expected < 0.035, "{actual} vs {expected}");
+        }
+        assert_eq!(p.max, 100_000.0);
+    }
+}
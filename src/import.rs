@@ -0,0 +1,114 @@
+// External series, such as the C# ConcurrentDictionary numbers from
+// map-bench-dot-net, read from CSV or JSON files and overlaid on the charts of
+// matching workloads.
+
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::results::Record;
+
+/// One point of an external series. A CSV file has these as columns and a
+/// JSON file is an array of them.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ImportRow {
+    /// The workload the point was measured on, see `Scenario::workload`.
+    workload: String,
+    name: String,
+    thread_count: u64,
+    /// Average latency in nanoseconds.
+    latency: f64,
+}
+
+impl From<ImportRow> for Record {
+    fn from(row: ImportRow) -> Self {
+        Record {
+            name: row.name,
+            adapter: None,
+            key_type: None,
+            thread_count: row.thread_count,
+            items: None,
+            target_rate: None,
+            latency: row.latency,
+            percentiles: None,
+            throughput: None,
+            spread: None,
+            counters: None,
+            footprint: None,
+        }
+    }
+}
+
+fn read_rows(path: &Path) -> Result<Vec<ImportRow>, String> {
+    let invalid = |e: &dyn std::fmt::Display| format!("Invalid series file {}: {}", path.display(), e);
+
+    if path.extension().is_some_and(|ext| ext == "json") {
+        let text = std::fs::read_to_string(path).map_err(|e| invalid(&e))?;
+        serde_json::from_str(&text).map_err(|e| invalid(&e))
+    } else {
+        let mut reader = csv::Reader::from_path(path).map_err(|e| invalid(&e))?;
+        reader
+            .deserialize()
+            .collect::<Result<Vec<ImportRow>, _>>()
+            .map_err(|e| invalid(&e))
+    }
+}
+
+fn series_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "csv" || ext == "json"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// The points for `workload` in every CSV and JSON file in `dir`. A missing
+/// directory has none.
+pub fn load_series(dir: &Path, workload: &str) -> Result<Vec<Record>, String> {
+    let mut records = Vec::new();
+    for path in series_files(dir)? {
+        let rows: Vec<ImportRow> = read_rows(&path)?
+            .into_iter()
+            .filter(|row| row.workload == workload)
+            .collect();
+        if !rows.is_empty() {
+            println!("Overlaying {} points for {} from {}", rows.len(), workload, path.display());
+        }
+        records.extend(rows.into_iter().map(Record::from));
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_series_by_workload() {
+        let dir = std::env::temp_dir().join(format!("map-bench-series-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("a.csv"),
+            "workload,name,thread_count,latency\nread100-10000,c#,1,50.5\nread99-insert1-10000,c#,1,55.0\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("b.json"),
+            r#"[{ "workload": "read100-10000", "name": "java", "thread_count": 2, "latency": 70.0 }]"#,
+        )
+        .unwrap();
+
+        let records = load_series(&dir, "read100-10000").unwrap();
+        let points: Vec<_> = records.iter().map(|r| (r.name.as_str(), r.thread_count, r.latency)).collect();
+        assert_eq!(points, vec![("c#", 1, 50.5), ("java", 2, 70.0)]);
+
+        assert!(load_series(&dir.join("missing"), "read100-10000").unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
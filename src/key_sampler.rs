@@ -0,0 +1,219 @@
+// Picks the index of the key each operation works on. Inserts take the next
+// of the thread's own new keys; the other operations pick an allocated key
+// according to the scenario's key distribution.
+
+use rand::Rng;
+use std::ops::Range;
+
+use crate::scenario::KeyDistribution;
+
+/// Theta of the Zipf fall off behind the latest inserted key.
+const LATEST_THETA: f64 = 0.99;
+
+/// Zipfian ranks 0..n, after Gray et al, "Quickly generating billion-record
+/// synthetic databases", as in YCSB. Rank 0 is the most likely.
+#[derive(Debug, Clone)]
+struct Zipf {
+    theta: f64,
+    n: usize,
+    alpha: f64,
+    zeta_2: f64,
+    zeta_n: f64,
+    eta: f64,
+}
+
+impl Zipf {
+    fn new(theta: f64, n: usize) -> Self {
+        let mut zipf = Zipf {
+            theta,
+            n: 0,
+            alpha: 1.0 / (1.0 - theta),
+            zeta_2: 1.0 + 0.5f64.powf(theta),
+            zeta_n: 0.0,
+            eta: 0.0,
+        };
+        zipf.grow(n);
+        zipf
+    }
+
+    /// Extends the ranks to 0..n, adding the new terms to zeta rather than
+    /// summing them all again.
+    fn grow(&mut self, n: usize) {
+        if n <= self.n {
+            return;
+        }
+        self.zeta_n += (self.n + 1..=n).map(|i| 1.0 / (i as f64).powf(self.theta)).sum::<f64>();
+        self.n = n;
+        self.eta = (1.0 - (2.0 / n as f64).powf(1.0 - self.theta)) / (1.0 - self.zeta_2 / self.zeta_n);
+    }
+
+    fn sample<R: Rng>(&self, rng: &mut R) -> usize {
+        let u: f64 = rng.gen();
+        let uz = u * self.zeta_n;
+        if uz < 1.0 || self.n < 2 {
+            0
+        } else if uz < self.zeta_2 {
+            1
+        } else {
+            let rank = self.n as f64 * (self.eta * u - self.eta + 1.0).powf(self.alpha);
+            (rank as usize).min(self.n - 1)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Picker {
+    Uniform,
+    Zipf(Zipf),
+    Hotspot { ops: u32, hot: usize },
+    /// Ranks back from the newest key: this thread's inserts, newest first,
+    /// then the prefill, last inserted first.
+    Latest(Zipf),
+    Sequential(usize),
+}
+
+/// Picks keys for one thread. Build one for the run with `new`, then one per
+/// thread with `for_thread`, before the timed region as the Zipf sums are slow.
+#[derive(Debug, Clone)]
+pub struct KeySampler {
+    picker: Picker,
+    prefill: usize,
+    key_count: usize,
+    new_keys: Range<usize>,
+    inserts: usize,
+}
+
+impl KeySampler {
+    /// `key_count` is the number of keys allocated once every thread has
+    /// taken its new keys, the first `prefill` of them already in the map.
+    pub fn new(distribution: KeyDistribution, prefill: usize, key_count: usize) -> Self {
+        let picker = match distribution {
+            KeyDistribution::Uniform => Picker::Uniform,
+            KeyDistribution::Zipf(theta) => Picker::Zipf(Zipf::new(theta, key_count)),
+            KeyDistribution::Hotspot { ops, keys } => Picker::Hotspot {
+                ops,
+                hot: (key_count * keys as usize / 100).max(1),
+            },
+            KeyDistribution::Latest => Picker::Latest(Zipf::new(LATEST_THETA, prefill)),
+            KeyDistribution::Sequential => Picker::Sequential(0),
+        };
+        KeySampler { picker, prefill, key_count, new_keys: 0..0, inserts: 0 }
+    }
+
+    /// A sampler for a thread inserting the keys of `new_keys`.
+    pub fn for_thread(&self, new_keys: Range<usize>) -> Self {
+        KeySampler { new_keys, ..self.clone() }
+    }
+
+    /// The keys this thread inserts.
+    pub fn new_keys(&self) -> Range<usize> {
+        self.new_keys.clone()
+    }
+
+    /// The key to insert next, going round the thread's new keys again once
+    /// they are used up.
+    pub fn next_new(&mut self) -> usize {
+        let index = self.new_keys.start + self.inserts % self.new_keys.len();
+        self.inserts += 1;
+        // Growing the ranks costs a powf or two, too much for every insert of
+        // the timed loop, so they grow as the inserts double. Until then the
+        // oldest prefill keys, far down the tail, aren't picked.
+        let own = self.inserts.min(self.new_keys.len());
+        if let Picker::Latest(zipf) = &mut self.picker {
+            if own.is_power_of_two() || own == self.new_keys.len() {
+                zipf.grow(self.prefill + own);
+            }
+        }
+        index
+    }
+
+    /// The key to read, update or remove.
+    pub fn next<R: Rng>(&mut self, rng: &mut R) -> usize {
+        match &mut self.picker {
+            Picker::Uniform => rng.gen::<usize>() % self.key_count,
+            Picker::Zipf(zipf) => zipf.sample(rng),
+            Picker::Hotspot { ops, hot } => {
+                let cold = self.key_count - *hot;
+                if cold == 0 || rng.gen_range(0..100) < *ops {
+                    rng.gen_range(0..*hot)
+                } else {
+                    *hot + rng.gen_range(0..cold)
+                }
+            }
+            Picker::Latest(zipf) => {
+                if zipf.n == 0 {
+                    return rng.gen::<usize>() % self.key_count;
+                }
+                let back = zipf.sample(rng);
+                let own = self.inserts.min(self.new_keys.len());
+                if back < own {
+                    let newest = (self.inserts - 1) % self.new_keys.len();
+                    let len = self.new_keys.len();
+                    self.new_keys.start + (newest + len - back) % len
+                } else {
+                    self.prefill - 1 - (back - own)
+                }
+            }
+            Picker::Sequential(next) => {
+                let index = *next % self.key_count;
+                *next += 1;
+                index
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(sampler: &mut KeySampler, picks: usize) -> Vec<usize> {
+        let mut rng = rand::thread_rng();
+        let mut counts = vec![0; sampler.key_count];
+        for _ in 0..picks {
+            counts[sampler.next(&mut rng)] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_distributions() {
+        let zipf = counts(&mut KeySampler::new(KeyDistribution::Zipf(0.99), 1000, 1000), 100_000);
+        assert!(zipf[0] > zipf[1] && zipf[1] > zipf[10] && zipf[10] > zipf[500]);
+        assert!(zipf[..10].iter().sum::<usize>() > 25_000);
+
+        let hotspot = KeyDistribution::Hotspot { ops: 90, keys: 10 };
+        let hotspot = counts(&mut KeySampler::new(hotspot, 1000, 1000), 100_000);
+        let hot: usize = hotspot[..100].iter().sum();
+        assert!((88_000..92_000).contains(&hot), "{hot} of 100000 on the hot keys");
+
+        let mut sequential = KeySampler::new(KeyDistribution::Sequential, 10, 20);
+        let mut rng = rand::thread_rng();
+        let picks: Vec<usize> = (0..25).map(|_| sequential.next(&mut rng)).collect();
+        assert_eq!(picks, (0..20).chain(0..5).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_latest_follows_inserts() {
+        let mut rng = rand::thread_rng();
+        let mut latest = KeySampler::new(KeyDistribution::Latest, 100, 300).for_thread(200..250);
+        let before: Vec<usize> = (0..1000).map(|_| latest.next(&mut rng)).collect();
+        assert!(before.iter().all(|&i| i < 100));
+        assert!(before.iter().filter(|&&i| i >= 90).count() > 400);
+
+        for _ in 0..20 {
+            latest.next_new();
+        }
+        let after: Vec<usize> = (0..1000).map(|_| latest.next(&mut rng)).collect();
+        assert!(after.iter().all(|&i| i < 100 || (200..220).contains(&i)));
+        assert!(after.iter().filter(|&&i| i == 219).count() > after.iter().filter(|&&i| i == 200).count());
+
+        let Picker::Latest(zipf) = &latest.picker else { unreachable!() };
+        assert_eq!(zipf.n, 116);
+        for _ in 0..30 {
+            latest.next_new();
+        }
+        let Picker::Latest(zipf) = &latest.picker else { unreachable!() };
+        assert_eq!(zipf.n, 150);
+    }
+}
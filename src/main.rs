@@ -1,66 +1,140 @@
 use perf::Measurement;
-use perf_dotnet_data::PERF_DATA_DOT_NET_99_10k;
-use perf_dotnet_data::PERF_DATA_DOT_NET_100_10K;
-use perf_dotnet_data::PERF_DATA_DOT_NET_100_1M;
-use perf_dotnet_data::PERF_DATA_DOT_NET_99_1M;
+use cli::{Command, FootprintArgs, MapArgs, Options, ScenarioArgs, SharedMapArgs, ThreadArgs};
 use perf_map::MapAdapter;
-use perf_map::{Keys, Mix, SharedMapTestConfig};
+use perf_map::{Mix, SharedMapTestConfig};
 use perf_mem::get_core_info;
 use perf_mem::AffinityType;
-use plotters::prelude::SVGBackend;
-use plotters::prelude::*;
-use std::collections::BTreeMap;
+use plot::{write_heatmap, write_plot, write_plots, write_rate_plots, write_timeline_plots, Chart, Metric, XAxis};
 use std::collections::HashMap;
-use std::error::Error;
-use std::sync::Arc;
-use std::time::Instant;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
 use thousands::Separable;
 use rand::Rng;
-
+use results::{Record, Results};
+use serde_json::json;
+use scenario::{KeyDistribution, KeyType, Scenario};
+use timeline::TimelineRecorder;
+
+mod affinity;
+mod allocator;
+mod cli;
+mod compare;
+mod histogram;
+mod key_sampler;
+mod import;
 mod map_adapters;
 mod perf;
-mod perf_dotnet_data;
+mod perf_counters;
 mod perf_info;
 mod perf_map;
 mod perf_mem;
+mod plot;
+mod report;
+mod results;
+mod scenario;
+mod numa_mem;
+mod palette;
 mod sfix;
+mod stats;
+mod timeline;
+#[cfg(target_os = "linux")]
+mod sysfs;
 mod numa_allocator;
 
-use crate::map_adapters::*;
-
-// Enable this to use mimalloc
-//#[global_allocator]
-//static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
+use crate::affinity::Pinning;
+use crate::map_adapters::registry::{self, Adapter, KeySet};
 
 fn main() {
-    perf_info::write_cpu_info();
-
-    run_map_op_test(Mix::read_100(), 1_000_000, &PERF_DATA_DOT_NET_100_1M);
-    run_map_op_test(Mix::read_100(), 10_000, &PERF_DATA_DOT_NET_100_10K);
-    run_map_op_test(Mix::read_99(), 1_000_000, &PERF_DATA_DOT_NET_99_1M);
-    run_map_op_test(Mix::read_99(), 10_000, &PERF_DATA_DOT_NET_99_10k);
-    run_map_key_test(Mix::read_99(), 1_000_000);
-    run_map_key_test(Mix::read_99(), 10_000);
-    run_map_key_test(Mix::read_100(), 100_000);
-    run_map_test();
-    run_mem_indirect_test();
-    run_fetch_add_test();
-    run_heapalloc_tests();
-    run_memory_read_write_test();
+    let options = Options::from_args();
+    let out_dir = options.out_dir.as_path();
+    let series_dir = options.series_dir.as_path();
+    std::fs::create_dir_all(out_dir).expect("Failed to create output directory");
+    palette::set_by_family(options.color_by_family);
+
+    println!("Global allocator: {}", allocator::NAME);
+    let run_info = results::run_info();
+    println!(
+        "Run started {} at commit {}",
+        run_info.timestamp,
+        run_info.git_commit.as_deref().unwrap_or("unknown")
+    );
+    if options.command.as_ref().is_none_or(Command::writes_cpu_info) {
+        perf_info::write_cpu_info(out_dir);
+    }
+
+    let result = match &options.command {
+        None | Some(Command::All) => run_all(series_dir, out_dir),
+        Some(Command::MapOp(args)) => run_map_op_test(args, series_dir, out_dir),
+        Some(Command::MapKey(args)) => run_map_key_test(args, out_dir),
+        Some(Command::Map(args)) => run_map_test(args, out_dir),
+        Some(Command::MemIndirect) => {
+            run_mem_indirect_test(out_dir);
+            Ok(())
+        }
+        Some(Command::FetchAdd(args)) => {
+            run_fetch_add_test(args, out_dir);
+            Ok(())
+        }
+        Some(Command::HeapAlloc(args)) => {
+            run_heapalloc_tests(args, out_dir);
+            Ok(())
+        }
+        Some(Command::MemRw(args)) => {
+            run_memory_read_write_test(args, out_dir);
+            Ok(())
+        }
+        Some(Command::Footprint(args)) => run_footprint_test(args, out_dir),
+        Some(Command::Scenario(args)) => run_scenario_files(args, series_dir, out_dir),
+        Some(Command::Compare(args)) => compare::run_compare(args, out_dir),
+        Some(Command::Report) => write_report(out_dir),
+        Some(Command::Maps) => {
+            for adapter in &registry::ADAPTERS {
+                println!("{:12} {}", adapter.name, adapter.description);
+            }
+            Ok(())
+        }
+        Some(Command::CpuInfo) => Ok(()),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+fn run_all(series_dir: &Path, out_dir: &Path) -> Result<(), String> {
+    let threads = ThreadArgs::default();
+
+    run_map_op_test(&SharedMapArgs::default(), series_dir, out_dir)?;
+    run_map_key_test(&SharedMapArgs::default(), out_dir)?;
+    run_map_test(&MapArgs::default(), out_dir)?;
+    run_footprint_test(&FootprintArgs::default(), out_dir)?;
+    run_mem_indirect_test(out_dir);
+    run_fetch_add_test(&threads, out_dir);
+    run_heapalloc_tests(&threads, out_dir);
+    run_memory_read_write_test(&threads, out_dir);
+    write_report(out_dir)
 }
 
-fn run_memory_read_write_test() {
+fn write_report(out_dir: &Path) -> Result<(), String> {
+    let path = report::write_report(out_dir)?;
+    println!("Report written to {}", path.display());
+    Ok(())
+}
+
+fn run_memory_read_write_test(args: &ThreadArgs, out_dir: &Path) {
     let mut measurements1 = Vec::new();
     let mut measurements2 = Vec::new();
     let mut measurements3 = Vec::new();
     let mut measurements4 = Vec::new();
     let mut measurements5 = Vec::new();
-    let num_cpus = perf_mem::get_num_cpus();
+    let config = json!({ "suite": "mem-rw", "threads": args.thread_counts() });
 
     const BIG_BLOCK_SIZE: usize = 8 * 1024 * 1024;
     const SMALL_BLOCK_SIZE: usize = 64 * 1024;
 
-    for i in 1..=num_cpus {
+    for i in args.thread_counts() {
         measurements1.push(perf_mem::run_independent_memory_access_test("normal", i, AffinityType::NoAffinity, false, BIG_BLOCK_SIZE));    
         measurements1.push(perf_mem::run_independent_memory_access_test("numa match", i, AffinityType::NumaNodeAffinity, false, BIG_BLOCK_SIZE));
         measurements1.push(perf_mem::run_independent_memory_access_test("numa miss", i, AffinityType::NumaMismatch, false, BIG_BLOCK_SIZE));
@@ -81,148 +155,319 @@ fn run_memory_read_write_test() {
         measurements5.push(perf_mem::run_independent_memory_access_test("8mb", i, AffinityType::NoAffinity, false, BIG_BLOCK_SIZE)); 
     }
 
-    write_plot(
+    write_plots(
         &measurements1,
+        &args.metrics,
+        &args.charts,
         "Independent Memory Reads and Writes (8MB blocks)",
-        "Average", "Threads",
+        XAxis::linear("Threads"),
+        out_dir,
         "memory-8mb-read-write.svg",
     )
     .expect("failed to plot");
+    Results::new(&config, &measurements1)
+        .write(out_dir, "memory-8mb-read-write.svg")
+        .expect("failed to write results");
 
-    write_plot(
+    write_plots(
         &measurements2,
+        &args.metrics,
+        &args.charts,
         "Independent Memory Reads (8MB blocks)",
-        "Average", "Threads",
+        XAxis::linear("Threads"),
+        out_dir,
         "memory-8mb-read.svg",
     )
     .expect("failed to plot");
+    Results::new(&config, &measurements2)
+        .write(out_dir, "memory-8mb-read.svg")
+        .expect("failed to write results");
 
-    write_plot(
+    write_plots(
         &measurements3,
+        &args.metrics,
+        &args.charts,
         "Independent Memory Reads and Writes (64k blocks)",
-        "Average", "Threads",
+        XAxis::linear("Threads"),
+        out_dir,
         "memory-64k-read-write.svg",
     )
     .expect("failed to plot");
+    Results::new(&config, &measurements3)
+        .write(out_dir, "memory-64k-read-write.svg")
+        .expect("failed to write results");
 
-    write_plot(
+    write_plots(
         &measurements4,
+        &args.metrics,
+        &args.charts,
         "Independent Memory Reads (64k blocks)",
-        "Average", "Threads",
+        XAxis::linear("Threads"),
+        out_dir,
         "memory-64k-read.svg",
     )
     .expect("failed to plot");
+    Results::new(&config, &measurements4)
+        .write(out_dir, "memory-64k-read.svg")
+        .expect("failed to write results");
 
-    write_plot(
+    write_plots(
         &measurements5,
+        &args.metrics,
+        &args.charts,
         "Independent Memory Reads and Writes (64k vs 8mb)",
-        "Average", "Threads",
+        XAxis::linear("Threads"),
+        out_dir,
         "memory-64k-8mb.svg",
     )
     .expect("failed to plot");
+    Results::new(&config, &measurements5)
+        .write(out_dir, "memory-64k-8mb.svg")
+        .expect("failed to write results");
 }
 
-fn run_heapalloc_tests()
+fn run_heapalloc_tests(args: &ThreadArgs, out_dir: &Path)
 {
     let mut measurements = Vec::new();
-    let num_cpus = perf_mem::get_num_cpus();
 
-    for i in 1..=num_cpus {
+    for i in args.thread_counts() {
         measurements.push(perf_mem::run_heapalloc_test("1 heap", i, 1));
         measurements.push(perf_mem::run_heapalloc_test("4 heaps", i, 4));
         measurements.push(perf_mem::run_heapalloc_test("16 heaps", i, 16));
     }
 
-    write_plot(
+    write_plots(
         &measurements,
+        &args.metrics,
+        &args.charts,
         "heap contention",
-        "Average", "Threads",
+        XAxis::linear("Threads"),
+        out_dir,
         "memory-allocators.svg",
     )
     .expect("failed to plot");
+    Results::new(&json!({ "suite": "heap-alloc", "threads": args.thread_counts() }), &measurements)
+        .write(out_dir, "memory-allocators.svg")
+        .expect("failed to write results");
 }
 
-fn run_fetch_add_test() {
+fn run_fetch_add_test(args: &ThreadArgs, out_dir: &Path) {
     let mut measurements1 = Vec::new();
     let mut measurements2 = Vec::new();
-    let num_cpus = perf_mem::get_num_cpus();
     let core_info = get_core_info().expect("Failed to get core IDs");
+    let config = json!({ "suite": "fetch-add", "threads": args.thread_counts() });
 
-    for i in 1..=num_cpus {
+    for i in args.thread_counts() {
         measurements2.push(perf_mem::run_fetch_add_test("atomic", i, 1));
         measurements2.push(perf_mem::run_mutex_test("mutex", i, 1));
 
-        measurements1.push(perf_mem::run_fetch_add_test("std alloc", i, core_info.num_numa_nodes));
+        measurements1.push(perf_mem::run_fetch_add_test("std alloc", i, core_info.numa_nodes.len()));
         measurements1.push(perf_mem::run_numa_fetch_add_test("numa aff", i, true));
         measurements1.push(perf_mem::run_numa_fetch_add_test("core aff", i, false));
     }
 
-    write_plot(
+    write_plots(
         &measurements1,
+        &args.metrics,
+        &args.charts,
         "Counter per Numa node (counter per numa node)",
-        "Average", "Threads",
+        XAxis::linear("Threads"),
+        out_dir,
         "memory-counter-atomic.svg",
     )
     .expect("failed to plot");
+    Results::new(&config, &measurements1)
+        .write(out_dir, "memory-counter-atomic.svg")
+        .expect("failed to write results");
 
-    write_plot(
+    write_plots(
         &measurements2,
+        &args.metrics,
+        &args.charts,
         "Global Counter (Mutex vs Atomic)",
-        "Average", "Threads",
+        XAxis::linear("Threads"),
+        out_dir,
         "memory-counter-mutex.svg",
     )
     .expect("failed to plot");
+    Results::new(&config, &measurements2)
+        .write(out_dir, "memory-counter-mutex.svg")
+        .expect("failed to write results");
 }
 
 
-fn run_map_op_test(spec: Mix, num_start_items : usize, dot_net : &Vec<Measurement>) {
-    let operations = spec.to_ops();    
-    let total_ops = 40_000_000;
-    let prefill = num_start_items;
-    let expected_inserts = total_ops * spec.insert / 100;
-    let capacity = num_start_items + expected_inserts;
-    let total_keys = prefill + expected_inserts + 1000; // 1000 needed for some rounding error?
+fn check_map_names(names: &[String], known: &[&str]) -> Result<(), String> {
+    for name in names {
+        if !known.contains(&name.as_str()) {
+            return Err(format!("unknown map '{}', expected one of {}", name, known.join(", ")));
+        }
+    }
+    Ok(())
+}
 
-    let mut measurements = dot_net.clone();
 
-    let keys = Arc::new(Keys::new(total_keys));
+fn run_map_op_test(args: &SharedMapArgs, series_dir: &Path, out_dir: &Path) -> Result<(), String> {
+    let maps = args.map_names(&["scc", "bfix", "nop"]);
+    registry::find_all(&maps)?;
 
-    for i in 0..perf_mem::get_num_cpus() {
-        let thread_count = i + 1;
-        let keys_needed_per_thread = expected_inserts / thread_count;
+    let runs = [(100, 1_000_000), (100, 10_000), (99, 1_000_000), (99, 10_000)];
+    let mut sweep = Vec::new();
+    for (read, prefill) in args.runs(&runs) {
+        let results = run_map_op(args, &maps, Mix::with_read(read), prefill, series_dir, out_dir)?;
+        sweep.push((format!("read {}% {}", read, prefill.separate_with_commas()), results));
+    }
+
+    if args.threads.charts.contains(&Chart::Heatmap) {
+        write_sweep_heatmaps(&maps, &sweep, &args.threads.metrics, out_dir)?;
+    }
+    Ok(())
+}
 
-        // Get the number of logical processors
+/// Charts each map over the read mixes and prefills it was run with, by
+/// thread count, e.g. `heatmap-scc.svg`.
+fn write_sweep_heatmaps(maps: &[String], sweep: &[(String, Results)], metrics: &[Metric], out_dir: &Path) -> Result<(), String> {
+    for map in maps {
+        for &metric in metrics {
+            let rows: Vec<(String, Vec<(u64, f64)>)> = sweep
+                .iter()
+                .map(|(run, results)| {
+                    let cells: Vec<(u64, f64)> = results
+                        .records
+                        .iter()
+                        .filter(|r| r.adapter.as_deref() == Some(map.as_str()))
+                        .filter_map(|r| Some((r.thread_count, metric.value(&r.measurement())?)))
+                        .collect();
+                    (run.clone(), cells)
+                })
+                .filter(|(_, cells)| !cells.is_empty())
+                .collect();
+            if rows.is_empty() {
+                continue;
+            }
+
+            let path = plot::chart_path(out_dir, &format!("heatmap-{}.svg", map), metric);
+            write_heatmap(rows, metric, &format!("{} by workload", map), "Threads", "", &path)
+                .map_err(|e| format!("Failed to plot {}: {}", path.display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+fn run_map_op(
+    args: &SharedMapArgs,
+    maps: &[String],
+    spec: Mix,
+    prefill: usize,
+    series_dir: &Path,
+    out_dir: &Path,
+) -> Result<Results, String> {
+    let scenario = Scenario {
+        title: None,
+        mix: spec,
+        key_type: KeyType::U64,
+        key_distribution: args.keys,
+        prefill,
+        total_ops: args.total_ops,
+        threads: args.threads.thread_counts(),
+        maps: maps.to_vec(),
+        pinning: args.pinning,
+        sample_every: args.sample_every,
+        warmup: args.warmup,
+        trials: args.trials,
+        reject_outliers: args.reject_outliers,
+        timeline_ms: args.timeline,
+        rates: args.rates.clone(),
+        verify: args.verify,
+        metrics: args.threads.metrics.clone(),
+        charts: args.threads.charts.clone(),
+        chart: format!("latency{}-{}{}.svg", spec.read, prefill, args.keys.file_suffix()),
+    };
+    scenario.validate()?;
+
+    run_scenario(&scenario, series_dir, out_dir)
+}
+
+fn run_scenario_files(args: &ScenarioArgs, series_dir: &Path, out_dir: &Path) -> Result<(), String> {
+    let scenarios = args
+        .files
+        .iter()
+        .map(|path| Scenario::load(path))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for scenario in &scenarios {
+        run_scenario(scenario, series_dir, out_dir)?;
+    }
+    Ok(())
+}
+
+/// Runs every map in the scenario over its thread counts and charts the
+/// results, along with any external series for the same workload.
+fn run_scenario(scenario: &Scenario, series_dir: &Path, out_dir: &Path) -> Result<Results, String> {
+    let adapters = registry::find_all(&scenario.maps)?;
+    let imported = import::load_series(series_dir, &scenario.workload())?;
+    let mut measurements: Vec<Measurement> = imported.iter().map(Record::measurement).collect();
+    let keys = KeySet::new(scenario.key_type, scenario.total_keys());
+    let operations = scenario.mix.to_ops();
+
+    let trials = scenario.trial_config();
+    let timelines = scenario
+        .timeline_ms
+        .map(|ms| TimelineRecorder::new(Duration::from_millis(ms)));
+
+    for thread_count in scenario.thread_counts() {
         let config = SharedMapTestConfig {
-            thread_count: i + 1,
-            total_ops,
+            thread_count,
+            total_ops: scenario.total_ops,
             operations: &operations,
-            keys_needed_per_thread,
-            prefill,
+            keys_needed_per_thread: scenario.expected_inserts() / thread_count,
+            key_distribution: scenario.key_distribution,
+            prefill: scenario.prefill,
+            pinning: scenario.pinning,
+            sample_every: scenario.sample_every,
+            timelines: timelines.as_ref(),
+            count_allocations: false,
+            target_rate: None,
+            verify: scenario.verify,
         };
 
-        let m = Arc::new(SccCollection::<u64, u64, ahash::RandomState>::with_capacity(capacity));
-        measurements.push(perf_map::run_shared_map_test(&"scc", m, &config, &keys));
+        for adapter in &adapters {
+            if scenario.rates.is_empty() {
+                measurements.push(trials.run(|| adapter.run(adapter.name, scenario.capacity(), &config, &keys)));
+            }
+            for &rate in &scenario.rates {
+                let config = SharedMapTestConfig { target_rate: Some(rate), ..config.clone() };
+                print!("{:>12} ops/s: ", rate.separate_with_commas());
+                measurements.push(trials.run(|| adapter.run(adapter.name, scenario.capacity(), &config, &keys)));
+            }
+        }
+    }
 
-        let m =
-            Arc::new(BFixCollection::<u64, u64, ahash::RandomState>::with_capacity(capacity));
-        measurements.push(perf_map::run_shared_map_test(&"bfix", m, &config, &keys));
+    let results = Results::new(scenario, &measurements)
+        .mark_maps(|name| scenario.maps.iter().any(|m| m == name).then(|| (name.to_string(), scenario.key_type)));
+    results.write(out_dir, &scenario.chart)?;
 
-        // let m =
-        //     Arc::new(StdHashMapCollection::<u64, u64, ahash::RandomState>::with_capacity(capacity));
-        // measurements.push(perf_map::run_workload(&"std", m, &config, &keys));
+    if !scenario.rates.is_empty() {
+        write_rate_plots(&measurements, &scenario.metrics, &scenario.title(), out_dir, &scenario.chart)
+            .map_err(|e| format!("Failed to plot the rates of {}: {}", scenario.chart, e))?;
+        return Ok(results);
+    }
 
-        let m =
-            Arc::new(NopCollection::<u64, u64, ahash::RandomState>::with_capacity(capacity));
-        measurements.push(perf_map::run_shared_map_test(&"nop", m, &config, &keys));
+    if let Some(timelines) = &timelines {
+        write_timeline_plots(&timelines.latest(), &scenario.title(), out_dir, &scenario.chart)
+            .map_err(|e| format!("Failed to plot the timelines of {}: {}", scenario.chart, e))?;
     }
 
-    write_plot(
+    write_plots(
         &measurements,
-        &format!("Average latency (read = {}%   items = {}+{})", spec.read, prefill.separate_with_commas(), expected_inserts.separate_with_commas()),
-        "Latency", "Threads",
-        &format!("latency{}-{}.svg", spec.read, num_start_items),
+        &scenario.metrics,
+        &scenario.charts,
+        &scenario.title(),
+        XAxis::linear("Threads"),
+        out_dir,
+        &scenario.chart,
     )
-    .expect("failed to plot");
+    .map_err(|e| format!("Failed to plot {}: {}", scenario.chart, e))?;
+    Ok(results)
 }
 
 type DefaultHashBuilder = core::hash::BuildHasherDefault<ahash::AHasher>;
@@ -284,37 +529,131 @@ where
     }
 }
 
-fn run_map_test() {
+fn run_map_test(args: &MapArgs, out_dir: &Path) -> Result<(), String> {
+    let maps = args.map_names();
+    check_map_names(&maps, &["std", "hb", "bfix", "sfix"])?;
 
     let mut measurements = Vec::new();
+    let total_ops = args.total_ops;
+
+    for prefill in args.prefills() {
+        for map in &maps {
+            let m = match map.as_str() {
+                "std" => {
+                    let mut std_map: HashMap<u64, u64, DefaultHashBuilder> = HashMap::with_capacity_and_hasher(prefill, DefaultHashBuilder::default());
+                    perf_map::run_map_test("std", total_ops, prefill, &mut std_map)
+                }
+                "hb" => {
+                    let mut hashbrown_map: hashbrown::HashMap<u64, u64, DefaultHashBuilder> = hashbrown::HashMap::with_capacity_and_hasher(prefill, DefaultHashBuilder::default());
+                    perf_map::run_map_test("hb", total_ops, prefill, &mut hashbrown_map)
+                }
+                "bfix" => {
+                    let mut bfix_map: bfixmap::BFixMap<u64, u64, DefaultHashBuilder> = bfixmap::BFixMap::with_capacity_and_hasher(prefill, DefaultHashBuilder::default());
+                    perf_map::run_map_test("bfix", total_ops, prefill, &mut bfix_map)
+                }
+                "sfix" => {
+                    let mut sfix_map: sfix::SFixMap<u64, u64, DefaultHashBuilder> = sfix::SFixMap::with_capacity_and_hasher(prefill, DefaultHashBuilder::default());
+                    perf_map::run_map_test("sfix", total_ops, prefill, &mut sfix_map)
+                }
+                _ => unreachable!("unknown map {map}"),
+            };
+            measurements.push(m);
+        }
+    }
 
-    for i in 0..20 {
+    write_plot(
+        &measurements,
+        Metric::Latency,
+        &"Non-shared maps (Average latency)",
+        XAxis::log("Items"),
+        &out_dir.join("maps.svg"),
+    )
+    .expect("failed to plot");
 
-        let prefill = (i + 1) * 5000;
+    let config = json!({ "suite": "map", "maps": maps, "prefill": args.prefills(), "total_ops": total_ops });
+    Results::new(&config, &measurements)
+        .mark_maps(|name| Some((name.to_string(), KeyType::U64)))
+        .write(out_dir, "maps.svg")
+}
 
-        let mut std_map: HashMap<u64, u64, DefaultHashBuilder> = HashMap::with_capacity_and_hasher(prefill, DefaultHashBuilder::default());
-        measurements.push(perf_map::run_map_test("std", 10_000_000, prefill, &mut std_map));
+/// Heap use of each map after prefill and after a short single threaded run,
+/// charted as bytes per entry and peak bytes by item count.
+fn run_footprint_test(args: &FootprintArgs, out_dir: &Path) -> Result<(), String> {
+    let maps = args.map_names();
+    let local_maps = ["std", "hb", "sfix"];
+    for name in &maps {
+        if !local_maps.contains(&name.as_str()) {
+            registry::find(name)?;
+        }
+    }
 
-        let mut hashbrown_map: hashbrown::HashMap<u64, u64, DefaultHashBuilder> = hashbrown::HashMap::with_capacity_and_hasher(prefill, DefaultHashBuilder::default());        
-        measurements.push(perf_map::run_map_test("hb", 10_000_000, prefill, &mut hashbrown_map));
+    let mix = Mix::with_read(args.read);
+    let operations = mix.to_ops();
+    let expected_inserts = args.total_ops * mix.insert / 100;
+    let mut measurements = Vec::new();
 
-        let mut bfix_map: bfixmap::BFixMap<u64, u64, DefaultHashBuilder> = bfixmap::BFixMap::with_capacity_and_hasher(prefill, DefaultHashBuilder::default());        
-        measurements.push(perf_map::run_map_test("bfix", 10_000_000, prefill, &mut bfix_map));
+    for prefill in args.prefills() {
+        let keys = KeySet::new(KeyType::U64, prefill + expected_inserts + 1000);
+        let config = SharedMapTestConfig {
+            thread_count: 1,
+            total_ops: args.total_ops,
+            operations: &operations,
+            keys_needed_per_thread: expected_inserts,
+            key_distribution: KeyDistribution::Uniform,
+            prefill,
+            pinning: Pinning::None,
+            sample_every: 16,
+            timelines: None,
+            count_allocations: true,
+            target_rate: None,
+            verify: false,
+        };
 
-        let mut sfix_map: sfix::SFixMap<u64, u64, DefaultHashBuilder> = sfix::SFixMap::with_capacity_and_hasher(prefill, DefaultHashBuilder::default());        
-        measurements.push(perf_map::run_map_test("sfix", 10_000_000, prefill, &mut sfix_map));
+        for name in &maps {
+            let mut m = match name.as_str() {
+                "std" => perf_map::run_map_footprint("std", prefill, || {
+                    HashMap::<u64, u64, DefaultHashBuilder>::with_capacity_and_hasher(prefill, DefaultHashBuilder::default())
+                }),
+                "hb" => perf_map::run_map_footprint("hb", prefill, || {
+                    hashbrown::HashMap::<u64, u64, DefaultHashBuilder>::with_capacity_and_hasher(prefill, DefaultHashBuilder::default())
+                }),
+                "sfix" => perf_map::run_map_footprint("sfix", prefill, || {
+                    sfix::SFixMap::<u64, u64, DefaultHashBuilder>::with_capacity_and_hasher(prefill, DefaultHashBuilder::default())
+                }),
+                _ => {
+                    let adapter = registry::find(name)?;
+                    adapter.run(adapter.name, prefill + expected_inserts, &config, &keys)
+                }
+            };
+            m.items = Some(prefill as u64);
+            measurements.push(m);
+        }
     }
 
-    write_plot(
+    let config = json!({
+        "suite": "footprint",
+        "maps": maps,
+        "prefill": args.prefills(),
+        "total_ops": args.total_ops,
+        "read": args.read,
+    });
+    Results::new(&config, &measurements)
+        .mark_maps(|name| Some((name.to_string(), KeyType::U64)))
+        .write(out_dir, "footprint.svg")?;
+
+    write_plots(
         &measurements,
-        &"Non-shared maps (Average latency)",
-        &"Latency", "K items",
-        &format!("maps.svg"),
+        &[Metric::BytesPerEntry, Metric::PeakBytes],
+        &[Chart::Lines],
+        "Memory footprint",
+        XAxis::log("Items"),
+        out_dir,
+        "footprint.svg",
     )
-    .expect("failed to plot");
+    .map_err(|e| format!("Failed to plot footprint.svg: {}", e))
 }
 
-fn run_mem_indirect_test() {
+fn run_mem_indirect_test(out_dir: &Path) {
 
     let mut measurements = Vec::new();
     let mut rng = rand::thread_rng();
@@ -349,11 +688,7 @@ fn run_mem_indirect_test() {
         let elapsed = start_time.elapsed();
         let average_duration = elapsed.as_nanos() as f64 / OP_COUNT as f64;
 
-        let m = Measurement {
-            name: "4",
-            latency: average_duration,
-            thread_count: i as u64,
-        };
+        let m = Measurement::new("4", average_duration, i as u64);
 
         measurements.push(m);
 
@@ -369,11 +704,7 @@ fn run_mem_indirect_test() {
         let elapsed = start_time.elapsed();
         let average_duration = elapsed.as_nanos() as f64 / OP_COUNT as f64;
 
-        let m = Measurement {
-            name: "3",
-            latency: average_duration,
-            thread_count: i as u64,
-        };
+        let m = Measurement::new("3", average_duration, i as u64);
 
         measurements.push(m);
 
@@ -388,11 +719,7 @@ fn run_mem_indirect_test() {
         let elapsed = start_time.elapsed();
         let average_duration = elapsed.as_nanos() as f64 / OP_COUNT as f64;
 
-        let m = Measurement {
-            name: "2",
-            latency: average_duration,
-            thread_count: i as u64,
-        };
+        let m = Measurement::new("2", average_duration, i as u64);
 
         measurements.push(m);
 
@@ -404,16 +731,30 @@ fn run_mem_indirect_test() {
 
     write_plot(
         &measurements,
+        Metric::Latency,
         &"Indirect memory access (MB blocks)",
-        &"Latency", "MB block size",
-        &format!("mem-indirect.svg"),
+        XAxis::log("MB block size"),
+        &out_dir.join("mem-indirect.svg"),
     )
     .expect("failed to plot");
+    Results::new(&json!({ "suite": "mem-indirect" }), &measurements)
+        .write(out_dir, "mem-indirect.svg")
+        .expect("failed to write results");
 }
 
-fn run_map_key_test(spec: Mix, num_start_items : usize) {
+fn run_map_key_test(args: &SharedMapArgs, out_dir: &Path) -> Result<(), String> {
+    let adapters = registry::find_all(&args.map_names(&["scc", "bfix"]))?;
+
+    let runs = [(99, 1_000_000), (99, 10_000), (100, 100_000)];
+    for (read, prefill) in args.runs(&runs) {
+        run_map_key(args, &adapters, Mix::with_read(read), prefill, out_dir);
+    }
+    Ok(())
+}
+
+fn run_map_key(args: &SharedMapArgs, adapters: &[&Adapter], spec: Mix, num_start_items: usize, out_dir: &Path) {
     let operations = spec.to_ops();
-    let total_ops = 40_000_000;
+    let total_ops = args.total_ops;
     let prefill = num_start_items;
     let expected_inserts = total_ops * spec.insert / 100;
     let capacity = num_start_items + expected_inserts;
@@ -421,152 +762,71 @@ fn run_map_key_test(spec: Mix, num_start_items : usize) {
 
     let mut measurements = Vec::new();
 
-    let keys1 = Arc::new(Keys::new(total_keys));
-    let keys2 = Arc::new(Keys::new(total_keys));
+    let keys1 = KeySet::new(KeyType::U64, total_keys);
+    let keys2 = KeySet::new(KeyType::String, total_keys);
+    let labels: Vec<(String, String)> = adapters
+        .iter()
+        .map(|a| (format!("{} u64", a.name), format!("{} str", a.name)))
+        .collect();
+
+    let trials = args.trial_config();
 
-    for i in 0..perf_mem::get_num_cpus() {
-        let thread_count = i + 1;
+    for thread_count in args.threads.thread_counts() {
         let keys_needed_per_thread = expected_inserts / thread_count;
 
         let config = SharedMapTestConfig {
-            thread_count: i + 1,
+            thread_count,
             total_ops,
             operations: &operations,
             keys_needed_per_thread,
+            key_distribution: args.keys,
             prefill,
+            pinning: args.pinning,
+            sample_every: args.sample_every,
+            timelines: None,
+            count_allocations: false,
+            target_rate: None,
+            verify: false,
         };
 
-        let scc1 = Arc::new(SccCollection::<u64, u64, ahash::RandomState>::with_capacity(capacity));
-        measurements.push(perf_map::run_shared_map_test(&"scc u64", scc1, &config, &keys1));
-
-        let scc2 = Arc::new(
-            SccCollection::<String, u64, ahash::RandomState>::with_capacity(capacity),
-        );
-        measurements.push(perf_map::run_shared_map_test(&"scc str", scc2, &config, &keys2));
-
-        let bfix1 =
-            Arc::new(BFixCollection::<u64, u64, ahash::RandomState>::with_capacity(capacity));
-        measurements.push(perf_map::run_shared_map_test(&"bfix u64", bfix1, &config, &keys1));
-
-        let bfix2 = Arc::new(
-            BFixCollection::<String, u64, ahash::RandomState>::with_capacity(capacity),
-        );
-        measurements.push(perf_map::run_shared_map_test(&"bfix str", bfix2, &config, &keys2));
+        for (adapter, (u64_label, str_label)) in adapters.iter().zip(&labels) {
+            measurements.push(trials.run(|| adapter.run(u64_label, capacity, &config, &keys1)));
+            measurements.push(trials.run(|| adapter.run(str_label, capacity, &config, &keys2)));
+        }
     }
 
-    write_plot(
+    let chart = format!("keys{}-{}{}.svg", spec.read, num_start_items, args.keys.file_suffix());
+    write_plots(
         &measurements,
+        &args.threads.metrics,
+        &args.threads.charts,
         &format!("String vs u64 keys latency (read = {}%   items = {}+{})", spec.read, prefill.separate_with_commas(), expected_inserts.separate_with_commas()),
-        "Latency", "Threads",
-        &format!("keys{}-{}.svg", spec.read, num_start_items),
+        XAxis::linear("Threads"),
+        out_dir,
+        &chart,
     )
     .expect("failed to plot");
-}
-
-const FONT: &str = "Fira Code";
-const PLOT_WIDTH: u32 = 800;
-const PLOT_HEIGHT: u32 = 400;
-
-pub fn write_plot(
-    records: &Vec<perf::Measurement>,
-    caption: &str,
-    y_label: &str,
-    x_label: &str,
-    path: &str,
-) -> Result<(), Box<dyn Error>> {
-    let mut groups: BTreeMap<&str, Vec<&perf::Measurement>> = BTreeMap::new();
-
-    let mut color_map = HashMap::new();
-    color_map.insert("bfix", GREEN);
-    color_map.insert("c#", RED);
-    color_map.insert("ev", GREEN);
-    color_map.insert("scc", BLUE);
-    color_map.insert("nop", CYAN);
-    color_map.insert("std", MAGENTA);
-    color_map.insert("hb", BLUE);
-    color_map.insert("sfix", RED);
-    color_map.insert("scc u64", RGBColor(10, 10, 240));
-    color_map.insert("scc str", RGBColor(10, 10, 180));
-    color_map.insert("bfix u64", RGBColor(10, 240, 10));
-    color_map.insert("bfix str", RGBColor(10, 180, 10));
-    color_map.insert("normal", RED);
-    color_map.insert("numa match", GREEN);
-    color_map.insert("numa miss", BLUE);
-    color_map.insert("std alloc", RED);
-    color_map.insert("atomic", RED);
-    color_map.insert("numa aff", BLUE);
-    color_map.insert("mutex", BLUE);
-    color_map.insert("core aff", MAGENTA);
-    color_map.insert("64k", RED);
-    color_map.insert("8mb", BLUE);
-    color_map.insert("1", BLUE);
-    color_map.insert("2", GREEN);
-    color_map.insert("3", RED);
-    color_map.insert("4", MAGENTA);
-    color_map.insert("1 heap", BLUE);
-    color_map.insert("4 heaps", GREEN);
-    color_map.insert("16 heaps", RED);
-
-    for record in records.iter() {
-        let group = groups.entry(record.name).or_insert_with(Vec::new);
-        group.push(&record);
-    }
-
-    let resolution = (PLOT_WIDTH, PLOT_HEIGHT);
-    let root = SVGBackend::new(&path, resolution).into_drawing_area();
-
-    root.fill(&WHITE)?;
-
-    
-    let y_min = records.iter().map(|m| m.latency).fold(f64::INFINITY, f64::min);
-    let y_max = records.iter().map(|m| m.latency).fold(f64::NEG_INFINITY, f64::max);
-    let y_diff = y_max - y_min;
-    let y_padding = (y_diff / 10.0).min(y_min);
-
-    let x_min = records.iter().map(|m| m.thread_count).min().unwrap();
-    let x_max = records.iter().map(|m| m.thread_count).max().unwrap();
-
-    
-    let mut chart = ChartBuilder::on(&root)
-        .margin(10)
-        .caption(caption, (FONT, 20))
-        .set_label_area_size(LabelAreaPosition::Left, 70)
-        .set_label_area_size(LabelAreaPosition::Right, 70)
-        .set_label_area_size(LabelAreaPosition::Bottom, 40)
-        .build_cartesian_2d(1..x_max, y_min - y_padding..y_max + y_padding)?;
-
-    chart
-        .configure_mesh()
-        .disable_y_mesh()
-        .x_label_formatter(&|v| format!("{}", v))
-        .y_label_formatter(&|v| format!("{:.0} ns", v))
-        .x_labels(20)
-        .y_labels(20)
-        .y_desc(y_label)
-        .x_desc(x_label)
-        .draw()?;
-
-    for records in groups.values() {
-        let color = color_map.get(records[0].name).unwrap();
-        chart
-            .draw_series(LineSeries::new(
-                records
-                    .iter()
-                    .map(|record| (record.thread_count, record.latency)),
-                color,
-            ))?
-            .label(records[0].name)
-            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
-    }
 
-    chart
-        .configure_series_labels()
-        .position(SeriesLabelPosition::UpperLeft)
-        .label_font((FONT, 13))
-        .background_style(WHITE.mix(0.8))
-        .border_style(BLACK)
-        .draw()?;
-
-    Ok(())
+    let config = json!({
+        "suite": "map-key",
+        "mix": spec,
+        "prefill": prefill,
+        "total_ops": total_ops,
+        "threads": args.threads.thread_counts(),
+        "maps": adapters.iter().map(|a| a.name).collect::<Vec<_>>(),
+        "pinning": args.pinning,
+        "key_distribution": args.keys,
+        "sample_every": args.sample_every,
+        "warmup": args.warmup,
+        "trials": args.trials,
+        "reject_outliers": args.reject_outliers,
+    });
+    Results::new(&config, &measurements)
+        .mark_maps(|name| {
+            let (adapter, key_type) = name.rsplit_once(' ')?;
+            let key_type = if key_type == "str" { KeyType::String } else { KeyType::U64 };
+            Some((adapter.to_string(), key_type))
+        })
+        .write(out_dir, &chart)
+        .expect("failed to write results");
 }
-
@@ -12,20 +12,6 @@ pub struct BFixHandle<K: Eq + Hash + Send + 'static + Default + Clone, V: Clone
     BFixMap<K, V, H>,
 );
 
-impl<K, V, H> BFixCollection<K, V, H>
-where
-    K: Send + Default + Sync + Eq + Hash + Clone + 'static,
-    V: Send + Sync + Clone + Default + ValueModifier + 'static,
-    H: Send + Sync + BuildHasher + Default + 'static + Clone + Default,
-{
-    pub fn with_capacity(capacity: usize) -> Self {
-        Self(BFixMap::with_capacity_and_hasher(
-            capacity,
-            H::default(),
-        ))
-    }
-}
-
 impl<K, V, H> BFixHandle<K, V, H>
 where
     K: Send + Sync + Eq + Hash + Clone + Eq + 'static + Default,
@@ -45,6 +31,13 @@ where
 {
     type Handle = BFixHandle<K, V, H>;
 
+    fn with_capacity(capacity: usize) -> Self {
+        Self(BFixMap::with_capacity_and_hasher(
+            capacity,
+            H::default(),
+        ))
+    }
+
     fn pin(&self) -> Self::Handle {
         Self::Handle::new(self.0.clone())
     }
@@ -75,4 +68,32 @@ where
     fn update(&self, key: &Self::Key) -> bool {
         self.0.modify(key, |v| { v.modify(); true }).is_some()
     }
+
+    // insert replaces the value and returns the one it replaced
+    fn upsert(&self, key: Self::Key) -> bool {
+        self.0.insert(key, V::default()).unwrap().is_some()
+    }
+
+    // BFixMap has nothing to make a value only when the key is missing.
+    // Composing these from a get or modify and an insert would race, so the
+    // registry refuses mixes that use them.
+    fn get_or_insert(&self, _key: Self::Key) -> bool {
+        unimplemented!("bfix has no get or insert")
+    }
+
+    fn compute(&self, _key: Self::Key) -> bool {
+        unimplemented!("bfix has no compute")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::perf_map::tests::Values;
+
+    impl Values for BFixCollection<u64, u64, ahash::RandomState> {
+        fn value(&self, key: u64) -> Option<u64> {
+            self.0.get(&key, |v| *v)
+        }
+    }
 }
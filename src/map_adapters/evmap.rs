@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::hash::{BuildHasher, Hash};
 use std::sync::{Arc, Mutex};
 
@@ -16,10 +17,61 @@ impl EvMeta {
     }
 }
 
+/// The write handle and the writes readers can't see yet. Refreshing
+/// publishes the whole map, so rather than refresh on every write the
+/// writer looks keys up here first.
+pub struct EvWriter<K: Eq + Hash + Clone, V: Eq + Hash + ShallowCopy, H: BuildHasher + Clone> {
+    handle: WriteHandle<K, V, EvMeta, H>,
+    // None for a removed key
+    pending: HashMap<K, Option<V>, H>,
+}
+
+impl<K, V, H> EvWriter<K, V, H>
+where
+    K: Eq + Hash + Clone,
+    V: Eq + Hash + ShallowCopy + Clone,
+    H: BuildHasher + Default + Clone,
+{
+    // readers see writes at most this many keys late
+    const REFRESH_EVERY: usize = 1024;
+
+    fn new(handle: WriteHandle<K, V, EvMeta, H>) -> Self {
+        Self {
+            handle,
+            pending: HashMap::with_hasher(H::default()),
+        }
+    }
+
+    /// The key's value including writes that are not published yet.
+    fn get(&self, key: &K) -> Option<V> {
+        match self.pending.get(key) {
+            Some(value) => value.clone(),
+            None => self.handle.get_one(key).map(|value| V::clone(&value)),
+        }
+    }
+
+    /// Replaces the key's values, or removes the key if `value` is None.
+    fn set(&mut self, key: K, value: Option<V>) {
+        match &value {
+            Some(value) => self.handle.update(key.clone(), value.clone()),
+            None => self.handle.empty(key.clone()),
+        };
+        self.pending.insert(key, value);
+        if self.pending.len() >= Self::REFRESH_EVERY {
+            self.refresh();
+        }
+    }
+
+    fn refresh(&mut self) {
+        self.handle.refresh();
+        self.pending.clear();
+    }
+}
+
 #[derive(Clone)]
 pub struct EvMapHandle<K: Eq + Hash + Clone, V: Eq + Hash + ShallowCopy, H: BuildHasher + Clone>(
     ReadHandle<K, V, EvMeta, H>,
-    Arc<Mutex<WriteHandle<K, V, EvMeta, H>>>,
+    Arc<Mutex<EvWriter<K, V, H>>>,
 );
 
 impl<K, V, H> EvMapHandle<K, V, H>
@@ -38,7 +90,7 @@ where
 {
     pub fn new(
         read_handle: ReadHandle<K, V, EvMeta, H>,
-        write_handle: Arc<Mutex<WriteHandle<K, V, EvMeta, H>>>,
+        write_handle: Arc<Mutex<EvWriter<K, V, H>>>,
     ) -> Self {
         Self(read_handle, write_handle)
     }
@@ -58,27 +110,6 @@ where
         + 'static,
     H: BuildHasher + Default + Send + Sync + Clone + 'static;
 
-impl<K, V, H> EvMapCollection<K, V, H>
-where
-    K: Send + Sync + Hash + Ord + Clone + FromU64 + 'static,
-    V: Eq
-        + Hash
-        + ShallowCopy
-        + Send
-        + Sync
-        + Clone        
-        + Default
-        + ValueModifier        
-        + 'static,
-    H: BuildHasher + Default + Send + Sync + Clone + 'static,
-{
-    pub fn with_capacity(_capacity: usize) -> Self {
-        let (r, w) = evmap::with_hasher::<K, V, EvMeta, H>(EvMeta::new(), H::default()); //(capacity, H::default());
-        let h = EvMapHandle::new(r, Arc::new(Mutex::new(w)));
-        Self(Arc::new(Mutex::new(h)))
-    }
-}
-
 unsafe impl<K, V, H> Sync for EvMapCollection<K, V, H>
 where
     K: Send + Sync + Hash + Ord + Clone + FromU64 + 'static,
@@ -107,10 +138,16 @@ where
         + Default
         + ValueModifier 
         + 'static,
-    H: BuildHasher + Default + Send + Sync + Clone + FromU64 + 'static,
+    H: BuildHasher + Default + Send + Sync + Clone + 'static,
 {
     type Handle = EvMapHandle<K, V, H>;
 
+    fn with_capacity(_capacity: usize) -> Self {
+        let (r, w) = evmap::with_hasher::<K, V, EvMeta, H>(EvMeta::new(), H::default()); //(capacity, H::default());
+        let h = EvMapHandle::new(r, Arc::new(Mutex::new(EvWriter::new(w))));
+        Self(Arc::new(Mutex::new(h)))
+    }
+
     fn pin(&self) -> Self::Handle {
         let h = self.0.lock().unwrap();
         EvMapHandle::new(h.0.clone(), h.1.clone())
@@ -122,6 +159,10 @@ where
         let mut w = h.1.lock().unwrap();
         w.refresh();
     }
+
+    fn len(&self) -> Option<usize> {
+        Some(self.0.lock().unwrap().0.len())
+    }
 }
 
 impl<K, V, H> CollectionHandle for EvMapHandle<K, V, H>
@@ -144,28 +185,74 @@ where
         self.0.get_one(&key).is_some()
     }
 
+    // The writes look the key up through the writer while holding its lock,
+    // so no other write can come between the two. evmap's insert adds to the
+    // key's values, so a present key is left alone.
     fn insert(&self, key: Self::Key) -> bool {
         let mut w = self.1.lock().unwrap();
-        w.insert(key, V::default());
+        if w.get(&key).is_some() {
+            return false;
+        }
+        w.set(key, Some(V::default()));
         true
     }
 
     fn remove(&self, key: &Self::Key) -> bool {
         let mut w = self.1.lock().unwrap();
-        w.clear(key.clone());
+        if w.get(key).is_none() {
+            return false;
+        }
+        w.set(key.clone(), None);
         true
     }
 
     fn update(&self, key: &Self::Key) -> bool {
-        if let Some(value) = self.0.get_one(&key) {
-            let mut v = value.clone();
-            v.modify();
-            drop(value);
-            let mut w = self.1.lock().unwrap();
-            w.update(key.clone(), v);
-            true
-        } else {
-            false
+        let mut w = self.1.lock().unwrap();
+        let Some(mut v) = w.get(key) else {
+            return false;
+        };
+        v.modify();
+        w.set(key.clone(), Some(v));
+        true
+    }
+
+    fn upsert(&self, key: Self::Key) -> bool {
+        let mut w = self.1.lock().unwrap();
+        let found = w.get(&key).is_some();
+        w.set(key, Some(V::default()));
+        found
+    }
+
+    fn get_or_insert(&self, key: Self::Key) -> bool {
+        let mut w = self.1.lock().unwrap();
+        if w.get(&key).is_some() {
+            return true;
+        }
+        w.set(key, Some(V::default()));
+        false
+    }
+
+    fn compute(&self, key: Self::Key) -> bool {
+        let mut w = self.1.lock().unwrap();
+        let (found, mut v) = match w.get(&key) {
+            Some(value) => (true, value),
+            None => (false, V::default()),
+        };
+        v.modify();
+        w.set(key, Some(v));
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::perf_map::tests::Values;
+
+    // as the writer sees it, published or not
+    impl Values for EvMapCollection<u64, u64, ahash::RandomState> {
+        fn value(&self, key: u64) -> Option<u64> {
+            self.0.lock().unwrap().1.lock().unwrap().get(&key)
         }
     }
 }
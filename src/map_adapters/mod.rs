@@ -1,5 +1,6 @@
 pub use self::{scc::SccCollection, stdmap::StdHashMapCollection, evmap::EvMapCollection, bfix::BFixCollection, nop::NopCollection };
 
+pub mod registry;
 mod scc;
 mod stdmap;
 mod evmap;
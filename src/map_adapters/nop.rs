@@ -6,17 +6,6 @@ use crate::perf_map::{Collection, CollectionHandle, FromU64, ValueModifier};
 #[derive(Clone)]
 pub struct NopCollection<K: Eq + Hash + Send + 'static, V, H: BuildHasher + 'static>(PhantomData<K>, PhantomData<V>, PhantomData<H>);
 
-impl<K, V, H> NopCollection<K, V, H>
-where
-    K: Send + Sync + Eq + Hash + Clone + FromU64 + 'static,
-    V: Send + Sync + Clone + Default + ValueModifier + 'static,
-    H: Send + Sync + BuildHasher + Default + 'static + Clone,
-{
-    pub fn with_capacity(_capacity: usize) -> Self {
-        Self(PhantomData, PhantomData, PhantomData)
-    }
-}
-
 pub struct NopHandle<K: Eq + Hash + Send + 'static, V, H: BuildHasher + 'static>(PhantomData<K>, PhantomData<V>, PhantomData<H>);
 
 impl<K, V, H> NopHandle<K, V, H>
@@ -38,6 +27,10 @@ where
 {
     type Handle = NopHandle<K, V, H>;
 
+    fn with_capacity(_capacity: usize) -> Self {
+        Self(PhantomData, PhantomData, PhantomData)
+    }
+
     fn pin(&self) -> Self::Handle {
         Self::Handle::new()
     }
@@ -70,4 +63,16 @@ where
     fn update(&self, _key: &Self::Key) -> bool {
         true
     }
+
+    fn upsert(&self, _key: Self::Key) -> bool {
+        true
+    }
+
+    fn get_or_insert(&self, _key: Self::Key) -> bool {
+        true
+    }
+
+    fn compute(&self, _key: Self::Key) -> bool {
+        true
+    }
 }
@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use crate::perf::Measurement;
+use crate::perf_map::{self, Collection, CollectionHandle, Keys, Mix, Operation, SharedMapTestConfig};
+use crate::scenario::KeyType;
+
+use super::{BFixCollection, EvMapCollection, NopCollection, SccCollection, StdHashMapCollection};
+
+type Hasher = ahash::RandomState;
+
+/// The keys for a run, of the type picked at runtime.
+pub enum KeySet {
+    U64(Arc<Keys<u64>>),
+    String(Arc<Keys<String>>),
+}
+
+impl KeySet {
+    pub fn new(key_type: KeyType, total_keys: usize) -> Self {
+        match key_type {
+            KeyType::U64 => KeySet::U64(Arc::new(Keys::new(total_keys))),
+            KeyType::String => KeySet::String(Arc::new(Keys::new(total_keys))),
+        }
+    }
+}
+
+type Runner<K> = for<'a> fn(&'a str, usize, &SharedMapTestConfig, &Arc<Keys<K>>) -> Measurement<'a>;
+
+/// A shared map that can be benchmarked, looked up by name from the command
+/// line or a scenario file.
+pub struct Adapter {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Operations the map has no single call for. Ones built from separate
+    /// calls would race, so the map can't run them.
+    pub unsupported: &'static [Operation],
+    run_u64: Runner<u64>,
+    run_string: Runner<String>,
+}
+
+impl Adapter {
+    pub fn supports(&self, op: Operation) -> bool {
+        !self.unsupported.contains(&op)
+    }
+
+    /// Fails if the mix has operations the map can't run.
+    pub fn check_mix(&self, mix: &Mix) -> Result<(), String> {
+        let missing: Vec<String> = mix
+            .percents()
+            .iter()
+            .filter(|&&(op, percent)| percent > 0 && !self.supports(op))
+            .map(|(op, _)| format!("{:?}", op).to_lowercase())
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        Err(format!("map '{}' has no atomic {}", self.name, missing.join(" or ")))
+    }
+
+    /// Creates the map with room for `capacity` items and runs the test on it.
+    pub fn run<'a>(
+        &self,
+        label: &'a str,
+        capacity: usize,
+        config: &SharedMapTestConfig,
+        keys: &KeySet,
+    ) -> Measurement<'a> {
+        match keys {
+            KeySet::U64(keys) => (self.run_u64)(label, capacity, config, keys),
+            KeySet::String(keys) => (self.run_string)(label, capacity, config, keys),
+        }
+    }
+}
+
+fn run<'a, C: Collection>(
+    label: &'a str,
+    capacity: usize,
+    config: &SharedMapTestConfig,
+    keys: &Arc<Keys<<C::Handle as CollectionHandle>::Key>>,
+) -> Measurement<'a> {
+    perf_map::run_shared_map_test(label, || C::with_capacity(capacity), config, keys)
+}
+
+pub static ADAPTERS: [Adapter; 5] = [
+    Adapter {
+        name: "scc",
+        description: "scc::HashMap, optimistic locking",
+        unsupported: &[],
+        run_u64: run::<SccCollection<u64, u64, Hasher>>,
+        run_string: run::<SccCollection<String, u64, Hasher>>,
+    },
+    Adapter {
+        name: "bfix",
+        description: "bfixmap::BFixMap, sharded simd probing",
+        unsupported: &[Operation::GetOrInsert, Operation::Compute],
+        run_u64: run::<BFixCollection<u64, u64, Hasher>>,
+        run_string: run::<BFixCollection<String, u64, Hasher>>,
+    },
+    Adapter {
+        name: "std-rwlock",
+        description: "std HashMap behind a parking_lot RwLock",
+        unsupported: &[],
+        run_u64: run::<StdHashMapCollection<u64, u64, Hasher>>,
+        run_string: run::<StdHashMapCollection<String, u64, Hasher>>,
+    },
+    Adapter {
+        name: "evmap",
+        description: "evmap, eventually consistent with a single writer",
+        unsupported: &[],
+        run_u64: run::<EvMapCollection<u64, u64, Hasher>>,
+        run_string: run::<EvMapCollection<String, u64, Hasher>>,
+    },
+    Adapter {
+        name: "nop",
+        description: "does nothing, to measure the test framework overhead",
+        unsupported: &[],
+        run_u64: run::<NopCollection<u64, u64, Hasher>>,
+        run_string: run::<NopCollection<String, u64, Hasher>>,
+    },
+];
+
+pub fn find(name: &str) -> Result<&'static Adapter, String> {
+    ADAPTERS.iter().find(|a| a.name == name).ok_or_else(|| {
+        let names: Vec<_> = ADAPTERS.iter().map(|a| a.name).collect();
+        format!("unknown map '{}', expected one of {}", name, names.join(", "))
+    })
+}
+
+pub fn find_all(names: &[String]) -> Result<Vec<&'static Adapter>, String> {
+    names.iter().map(|name| find(name)).collect()
+}
@@ -1,35 +1,22 @@
 use std::hash::{BuildHasher, Hash};
 use std::sync::Arc;
 
+use scc::hash_map::Entry;
+
 use crate::perf_map::{Collection, CollectionHandle, FromU64, ValueModifier};
 
 
 #[derive(Clone)]
 pub struct SccCollection<K, V, H: BuildHasher>(
-    scc::HashMap<K, V, H>,
+    Arc<scc::HashMap<K, V, H>>,
 
 )where
 K: Send + Sync + Eq + Hash + Clone + FromU64 + 'static,
 V: Send + Sync + Clone + Default + ValueModifier + 'static,
 H: Send + Sync + BuildHasher + Default + 'static + Clone;
 
-impl<K, V, H> SccCollection<K, V, H>
-where
-    K: Send + Sync + Eq + Hash + Clone + FromU64 + 'static,
-    V: Send + Sync + Clone + Default + ValueModifier + 'static,
-    H: Send + Sync + BuildHasher + Default + 'static + Clone,
-{
-    pub fn with_capacity(capacity: usize) -> Self {
-        scc::ebr::Guard::new().accelerate();
-        Self(scc::HashMap::with_capacity_and_hasher(
-            capacity,
-            H::default(),
-        ))
-    }
-}
-
 pub struct SccHandle<K, V, H: BuildHasher>(
-    scc::HashMap<K, V, H>,
+    Arc<scc::HashMap<K, V, H>>,
 );
 
 impl<K, V, H> SccHandle<K, V, H>
@@ -38,7 +25,7 @@ where
     V: Send + Sync + Clone + Default + ValueModifier + 'static,
     H: Send + Sync + BuildHasher + Default + 'static + Clone,
 {
-    pub fn new(m: scc::HashMap<K, V, H>) -> Self {
+    pub fn new(m: Arc<scc::HashMap<K, V, H>>) -> Self {
         Self(
             m,
         )
@@ -53,6 +40,14 @@ where
 {
     type Handle = SccHandle<K, V, H>;
 
+    fn with_capacity(capacity: usize) -> Self {
+        scc::ebr::Guard::new().accelerate();
+        Self(Arc::new(scc::HashMap::with_capacity_and_hasher(
+            capacity,
+            H::default(),
+        )))
+    }
+
     fn pin(&self) -> Self::Handle {
         Self::Handle::new(self.0.clone())
     }
@@ -60,6 +55,10 @@ where
     fn prefill_complete(&self)
     {
     }
+
+    fn len(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
 }
 
 impl<K, V, H> CollectionHandle for SccHandle<K, V, H>
@@ -85,5 +84,45 @@ where
     fn update(&self, key: &Self::Key) -> bool {
         self.0.update(&key, |_, v| v.modify()).is_some()
     }
+
+    fn upsert(&self, key: Self::Key) -> bool {
+        self.0.upsert(key, V::default()).is_some()
+    }
+
+    fn get_or_insert(&self, key: Self::Key) -> bool {
+        match self.0.entry(key) {
+            Entry::Occupied(_) => true,
+            Entry::Vacant(entry) => {
+                entry.insert_entry(V::default());
+                false
+            }
+        }
+    }
+
+    fn compute(&self, key: Self::Key) -> bool {
+        match self.0.entry(key) {
+            Entry::Occupied(mut entry) => {
+                entry.get_mut().modify();
+                true
+            }
+            Entry::Vacant(entry) => {
+                let mut v = V::default();
+                v.modify();
+                entry.insert_entry(v);
+                false
+            }
+        }
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::perf_map::tests::Values;
+
+    impl Values for SccCollection<u64, u64, ahash::RandomState> {
+        fn value(&self, key: u64) -> Option<u64> {
+            self.0.read(&key, |_, v| *v)
+        }
+    }
+}
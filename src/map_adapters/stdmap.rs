@@ -1,3 +1,4 @@
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::hash::{BuildHasher, Hash};
 use std::sync::Arc;
@@ -12,20 +13,6 @@ pub struct StdHashMapCollection<K: Eq + Hash + Send + 'static, V, H: BuildHasher
     Arc<Lock<HashMap<K, V, H>>>,
 );
 
-impl<K, V, H> StdHashMapCollection<K, V, H>
-where
-    K: Send + Sync + Eq + Hash + Clone + FromU64 + 'static,
-    V: Send + Sync + Clone + Default + ValueModifier + 'static,
-    H: Send + Sync + BuildHasher + Default + 'static + Clone,
-{
-    pub fn with_capacity(capacity: usize) -> Self {
-        Self(Arc::new(Lock::new(HashMap::with_capacity_and_hasher(
-            capacity,
-            H::default(),
-        ))))
-    }
-}
-
 pub struct StdHashMapHandle<K: Eq + Hash + Send + 'static, V, H: BuildHasher + 'static>(
     Arc<Lock<HashMap<K, V, H>>>,
 );
@@ -49,6 +36,13 @@ where
 {
     type Handle = StdHashMapHandle<K, V, H>;
 
+    fn with_capacity(capacity: usize) -> Self {
+        Self(Arc::new(Lock::new(HashMap::with_capacity_and_hasher(
+            capacity,
+            H::default(),
+        ))))
+    }
+
     fn pin(&self) -> Self::Handle {
         Self::Handle::new(self.0.clone())
     }
@@ -56,6 +50,10 @@ where
     fn prefill_complete(&self)
     {
     }
+
+    fn len(&self) -> Option<usize> {
+        Some(self.0.read().len())
+    }
 }
 
 impl<K, V, H> CollectionHandle for StdHashMapHandle<K, V, H>
@@ -88,4 +86,42 @@ where
             false
         }
     }
+
+    fn upsert(&self, key: Self::Key) -> bool {
+        self.0.write().insert(key, V::default()).is_some()
+    }
+
+    fn get_or_insert(&self, key: Self::Key) -> bool {
+        match self.0.write().entry(key) {
+            Entry::Occupied(_) => true,
+            Entry::Vacant(entry) => {
+                entry.insert(V::default());
+                false
+            }
+        }
+    }
+
+    fn compute(&self, key: Self::Key) -> bool {
+        let mut w = self.0.write();
+        let mut found = true;
+        w.entry(key)
+            .or_insert_with(|| {
+                found = false;
+                V::default()
+            })
+            .modify();
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::perf_map::tests::Values;
+
+    impl Values for StdHashMapCollection<u64, u64, ahash::RandomState> {
+        fn value(&self, key: u64) -> Option<u64> {
+            self.0.read().get(&key).copied()
+        }
+    }
 }
@@ -1,23 +1,24 @@
 use std::alloc::{GlobalAlloc, Layout};
-use std::os::raw::c_void;
 use std::ptr::null_mut;
 use std::sync::atomic::{AtomicPtr, Ordering};
-use winapi::um::heapapi::{HeapAlloc, HeapCreate, HeapDestroy, HeapFree, HeapReAlloc};
-use winapi::um::processthreadsapi::GetCurrentThread;
-use winapi::um::processtopologyapi::GetThreadGroupAffinity;
-use winapi::um::winnt::{GROUP_AFFINITY, HANDLE, HEAP_ZERO_MEMORY};
+
+use self::os::{create_heap, delete_heap, get_current_thread_numa_node, heap_alloc, heap_free, HeapHandle};
 
 const MAX_NUMA_NODES: usize = 8;
 const COOKIE_SIZE: usize = std::mem::size_of::<HeapCookie>();
 
+// Sized to a multiple of 16 so payloads keep the 16 byte alignment malloc gives
+#[repr(align(16))]
 struct HeapCookie {
-    heap_handle: winapi::um::winnt::HANDLE,
+    heap_handle: HeapHandle,
     allocated_ptr: *mut u8,
+    allocated_size: usize,
 }
 
-// Global allocator implementation
-struct NumaAwareAllocator {
-    heaps: [AtomicPtr<c_void>; MAX_NUMA_NODES],
+// Global allocator implementation, installed by allocator.rs with the
+// `alloc-numa` feature
+pub struct NumaAwareAllocator {
+    heaps: [AtomicPtr<os::Heap>; MAX_NUMA_NODES],
 }
 
 unsafe impl GlobalAlloc for NumaAwareAllocator {
@@ -36,9 +37,10 @@ unsafe impl GlobalAlloc for NumaAwareAllocator {
             let cookie_ptr = ptr.sub(COOKIE_SIZE) as *mut HeapCookie;
             let heap_handle = (*cookie_ptr).heap_handle;
             let allocated_ptr = (*cookie_ptr).allocated_ptr;
+            let allocated_size = (*cookie_ptr).allocated_size;
 
             // Free the memory using the correct heap
-            HeapFree(heap_handle, 0, allocated_ptr.cast::<c_void>());
+            heap_free(heap_handle, allocated_ptr, allocated_size);
         }
     }
 
@@ -51,34 +53,23 @@ unsafe impl GlobalAlloc for NumaAwareAllocator {
 
     #[inline]
     unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // Stay on the heap the block came from, whichever thread resizes it
         let cookie_ptr = ptr.sub(COOKIE_SIZE) as *mut HeapCookie;
         let heap_handle = (*cookie_ptr).heap_handle;
-        let old_allocated_ptr = (*cookie_ptr).allocated_ptr;
-
-        let offset = ptr.offset_from(old_allocated_ptr) as usize;
 
-        let alignment = layout.align();
-        let aligned_new_size = new_size + alignment + COOKIE_SIZE;
-        let allocated_ptr = unsafe { HeapReAlloc(heap_handle, 0, old_allocated_ptr.cast::<c_void>(), aligned_new_size).cast::<u8>() };
-        let new_ptr = allocated_ptr.add(offset);
-
-        // Store the cookie at the beginning
-        let cookie_ptr = new_ptr.sub(COOKIE_SIZE).cast::<HeapCookie>();
-        (*cookie_ptr).heap_handle = heap_handle;
-        (*cookie_ptr).allocated_ptr = allocated_ptr;
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_ptr = raw_allocate(new_layout, heap_handle, false);
+        if !new_ptr.is_null() {
+            std::ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+        }
 
         new_ptr
     }
 }
 
 #[inline]
-unsafe fn raw_allocate(
-    layout: Layout,
-    heap: *mut std::ffi::c_void,
-    zeroed: bool,
-) -> *mut u8 {
-    let flags = if zeroed { HEAP_ZERO_MEMORY } else { 0 };
-
+unsafe fn raw_allocate(layout: Layout, heap: HeapHandle, zeroed: bool) -> *mut u8 {
     // Calculate the total size needed, including space for alignment padding if necessary
     let total_size = layout.size() + COOKIE_SIZE;
     let alignment = layout.align();
@@ -88,7 +79,7 @@ unsafe fn raw_allocate(
     let aligned_total_size = total_size + alignment_padding;
 
     // Allocate memory
-    let allocated_ptr = HeapAlloc(heap, flags, aligned_total_size).cast::<u8>();
+    let allocated_ptr = heap_alloc(heap, aligned_total_size, zeroed);
     if allocated_ptr.is_null() {
         return std::ptr::null_mut();
     }
@@ -102,6 +93,7 @@ unsafe fn raw_allocate(
     let cookie_ptr = aligned_ptr.sub(COOKIE_SIZE).cast::<HeapCookie>();
     (*cookie_ptr).heap_handle = heap;
     (*cookie_ptr).allocated_ptr = allocated_ptr;
+    (*cookie_ptr).allocated_size = aligned_total_size;
 
     aligned_ptr
 }
@@ -109,11 +101,11 @@ unsafe fn raw_allocate(
 impl NumaAwareAllocator {
 
     #[inline]
-    fn get_heap(&self, numa_node: usize) -> *mut std::ffi::c_void {
+    fn get_heap(&self, numa_node: usize) -> HeapHandle {
         // Attempt to initialize the heap pointer if it's null
         let mut current_heap_ptr = self.heaps[numa_node].load(Ordering::Relaxed);
         while current_heap_ptr.is_null() {
-            let new_heap_ptr = create_heap();
+            let new_heap_ptr = create_heap(numa_node);
             let result = self.heaps[numa_node].compare_exchange_weak(
                 std::ptr::null_mut(),
                 new_heap_ptr,
@@ -138,52 +130,296 @@ impl NumaAwareAllocator {
     }
 }
 
-const NULL_PTR: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+const NULL_PTR: AtomicPtr<os::Heap> = AtomicPtr::new(std::ptr::null_mut());
 
-// Initialize the global allocator
-#[global_allocator]
-static ALLOCATOR: NumaAwareAllocator = NumaAwareAllocator {
-    heaps: [NULL_PTR; MAX_NUMA_NODES],
-};
+impl NumaAwareAllocator {
+    #[cfg_attr(not(feature = "alloc-numa"), allow(dead_code))]
+    pub const fn new() -> Self {
+        Self {
+            heaps: [NULL_PTR; MAX_NUMA_NODES],
+        }
+    }
+}
 
-#[inline]
-fn get_current_thread_numa_node() -> usize {
-    unsafe {
-        let mut group_affinity: GROUP_AFFINITY = std::mem::zeroed();
-        let result = GetThreadGroupAffinity(GetCurrentThread(), &mut group_affinity);
+/// A private heap, used to measure allocator contention directly.
+pub struct Heap(HeapHandle);
 
-        if result == 0 {
-            // Handle the error appropriately, perhaps by returning a default value or panicking
-            panic!("GetThreadGroupAffinity failed");
-        }
+unsafe impl Send for Heap {}
+unsafe impl Sync for Heap {}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self(create_heap(get_current_thread_numa_node()))
+    }
 
-        // Extract the NUMA node from the group affinity mask
-        // Long term need to check if each group maps to a NUMA node
-        let numa_node_mask = group_affinity.Mask;
-        numa_node_mask.trailing_zeros() as usize % MAX_NUMA_NODES
+    pub fn alloc(&self, size: usize) -> *mut u8 {
+        unsafe { heap_alloc(self.0, size, false) }
+    }
+
+    /// # Safety
+    /// `ptr` must come from `alloc` on this heap with the same `size`.
+    pub unsafe fn free(&self, ptr: *mut u8, size: usize) {
+        heap_free(self.0, ptr, size)
     }
 }
 
-#[inline]
-fn create_heap() -> HANDLE {
-    unsafe {
-        let heap_handle = HeapCreate(0, 0, 0);
+impl Drop for Heap {
+    fn drop(&mut self) {
+        delete_heap(self.0);
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod os {
+    use winapi::um::heapapi::{HeapAlloc, HeapCreate, HeapDestroy, HeapFree};
+    use winapi::um::processthreadsapi::GetCurrentProcessorNumberEx;
+    use winapi::um::winbase::GetNumaProcessorNodeEx;
+    use winapi::um::winnt::{HEAP_ZERO_MEMORY, PROCESSOR_NUMBER};
+
+    use super::MAX_NUMA_NODES;
+
+    pub type Heap = winapi::ctypes::c_void;
+    pub type HeapHandle = *mut Heap;
+
+    #[inline]
+    pub unsafe fn heap_alloc(heap: HeapHandle, size: usize, zeroed: bool) -> *mut u8 {
+        let flags = if zeroed { HEAP_ZERO_MEMORY } else { 0 };
+        HeapAlloc(heap, flags, size).cast::<u8>()
+    }
+
+    #[inline]
+    pub unsafe fn heap_free(heap: HeapHandle, ptr: *mut u8, _size: usize) {
+        HeapFree(heap, 0, ptr.cast());
+    }
+
+    // The node of the processor the thread is running on right now.
+    #[inline]
+    pub fn get_current_thread_numa_node() -> usize {
+        unsafe {
+            let mut processor: PROCESSOR_NUMBER = std::mem::zeroed();
+            GetCurrentProcessorNumberEx(&mut processor);
 
-        if heap_handle.is_null() {
-            panic!("HeapCreate failed for NUMA node");
+            let mut numa_node: u16 = 0;
+            if GetNumaProcessorNodeEx(&mut processor, &mut numa_node) == 0 {
+                return 0;
+            }
+
+            numa_node as usize % MAX_NUMA_NODES
         }
+    }
+
+    #[inline]
+    pub fn create_heap(_numa_node: usize) -> HeapHandle {
+        unsafe {
+            let heap_handle = HeapCreate(0, 0, 0);
+
+            if heap_handle.is_null() {
+                panic!("HeapCreate failed for NUMA node");
+            }
+
+            heap_handle
+        }
+    }
 
-        heap_handle
+    #[inline]
+    pub fn delete_heap(heap_ptr: HeapHandle) {
+        unsafe {
+            if HeapDestroy(heap_ptr) == 0 {
+                // Error handling: HeapDestroy failed
+                // You might want to panic, log the error, or return a Result
+                panic!("HeapDestroy failed!");
+            }
+        }
     }
 }
 
-#[inline]
-fn delete_heap(heap_ptr: HANDLE) {
-    unsafe {
-        if HeapDestroy(heap_ptr) == 0 {
-            // Error handling: HeapDestroy failed
-            // You might want to panic, log the error, or return a Result
-            panic!("HeapDestroy failed!");
+// Each heap is an arena of 4MB chunks bound to one numa node with mbind.
+// Small blocks come from power of two size classes with a free list per
+// class, large blocks are mapped directly.
+#[cfg(target_os = "linux")]
+mod os {
+    use std::os::raw::c_void;
+    use std::ptr::null_mut;
+    use std::sync::Mutex;
+
+    use super::MAX_NUMA_NODES;
+    use crate::numa_mem;
+
+    const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+    const CHUNK_HEADER: usize = 64;
+    const PAGE_SIZE: usize = 4096;
+    const MIN_CLASS_BITS: u32 = 4;
+    const NUM_CLASSES: usize = 15;
+    const MAX_SMALL: usize = 1 << (MIN_CLASS_BITS as usize + NUM_CLASSES - 1);
+
+    struct FreeBlock {
+        next: *mut FreeBlock,
+    }
+
+    struct ArenaState {
+        free: [*mut FreeBlock; NUM_CLASSES],
+        chunks: *mut u8,
+        cursor: *mut u8,
+        end: *mut u8,
+    }
+
+    unsafe impl Send for ArenaState {}
+
+    pub struct Heap {
+        numa_node: u32,
+        state: Mutex<ArenaState>,
+    }
+
+    pub type HeapHandle = *mut Heap;
+
+    #[inline]
+    fn size_class(size: usize) -> usize {
+        let bits = size.max(1 << MIN_CLASS_BITS).next_power_of_two().trailing_zeros();
+        (bits - MIN_CLASS_BITS) as usize
+    }
+
+    fn map_on_node(size: usize, numa_node: u32) -> *mut u8 {
+        let ptr = unsafe {
+            libc::mmap(
+                null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+
+        if ptr == libc::MAP_FAILED {
+            return null_mut();
+        }
+
+        // a node that can't be bound (e.g. no memory) falls back to the default policy
+        let _ = numa_mem::bind(ptr, size, numa_node);
+        ptr as *mut u8
+    }
+
+    fn unmap(ptr: *mut u8, size: usize) {
+        unsafe { libc::munmap(ptr as *mut c_void, size) };
+    }
+
+    impl Heap {
+        unsafe fn alloc_small(&self, class: usize) -> *mut u8 {
+            let block_size = 1 << (class + MIN_CLASS_BITS as usize);
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+            let head = state.free[class];
+            if !head.is_null() {
+                state.free[class] = (*head).next;
+                return head as *mut u8;
+            }
+
+            // blocks are aligned to their size, up to a page
+            let align = block_size.min(PAGE_SIZE);
+            let mut block = (state.cursor as usize + align - 1) & !(align - 1);
+
+            if state.cursor.is_null() || block + block_size > state.end as usize {
+                let chunk = map_on_node(CHUNK_SIZE, self.numa_node);
+                if chunk.is_null() {
+                    return null_mut();
+                }
+
+                // chain the chunks together so the heap can be destroyed
+                *(chunk as *mut *mut u8) = state.chunks;
+                state.chunks = chunk;
+                state.end = chunk.add(CHUNK_SIZE);
+                block = (chunk as usize + CHUNK_HEADER + align - 1) & !(align - 1);
+            }
+
+            state.cursor = (block + block_size) as *mut u8;
+            block as *mut u8
+        }
+
+        unsafe fn free_small(&self, ptr: *mut u8, class: usize) {
+            let block = ptr as *mut FreeBlock;
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            (*block).next = state.free[class];
+            state.free[class] = block;
+        }
+    }
+
+    #[inline]
+    pub unsafe fn heap_alloc(heap: HeapHandle, size: usize, zeroed: bool) -> *mut u8 {
+        if size > MAX_SMALL {
+            // fresh mappings are already zeroed
+            return map_on_node(size, (*heap).numa_node);
+        }
+
+        let ptr = (*heap).alloc_small(size_class(size));
+        if zeroed && !ptr.is_null() {
+            std::ptr::write_bytes(ptr, 0, size);
+        }
+        ptr
+    }
+
+    #[inline]
+    pub unsafe fn heap_free(heap: HeapHandle, ptr: *mut u8, size: usize) {
+        if size > MAX_SMALL {
+            unmap(ptr, size);
+        } else {
+            (*heap).free_small(ptr, size_class(size));
+        }
+    }
+
+    #[inline]
+    pub fn get_current_thread_numa_node() -> usize {
+        let mut cpu: u32 = 0;
+        let mut numa_node: u32 = 0;
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_getcpu,
+                &mut cpu as *mut u32,
+                &mut numa_node as *mut u32,
+                null_mut::<c_void>(),
+            )
+        };
+
+        if result != 0 {
+            return 0;
+        }
+
+        numa_node as usize % MAX_NUMA_NODES
+    }
+
+    #[inline]
+    pub fn create_heap(numa_node: usize) -> HeapHandle {
+        let heap = map_on_node(std::mem::size_of::<Heap>(), numa_node as u32) as HeapHandle;
+        if heap.is_null() {
+            panic!("Failed to create heap for NUMA node {numa_node}");
+        }
+
+        unsafe {
+            heap.write(Heap {
+                numa_node: numa_node as u32,
+                state: Mutex::new(ArenaState {
+                    free: [null_mut(); NUM_CLASSES],
+                    chunks: null_mut(),
+                    cursor: null_mut(),
+                    end: null_mut(),
+                }),
+            });
+        }
+
+        heap
+    }
+
+    #[inline]
+    pub fn delete_heap(heap: HeapHandle) {
+        unsafe {
+            let mut chunk = (*heap).state.get_mut().unwrap_or_else(|e| e.into_inner()).chunks;
+            while !chunk.is_null() {
+                let next = *(chunk as *mut *mut u8);
+                unmap(chunk, CHUNK_SIZE);
+                chunk = next;
+            }
+
+            heap.drop_in_place();
+            unmap(heap as *mut u8, std::mem::size_of::<Heap>());
         }
     }
 }
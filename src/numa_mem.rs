@@ -0,0 +1,278 @@
+#[cfg(target_os = "linux")]
+pub(crate) use self::os::bind;
+
+/// A block of memory allocated directly from the OS, optionally bound to a
+/// numa node. The memory is released when the block is dropped.
+pub struct NumaBlock {
+    ptr: *mut u8,
+    size: usize,
+}
+
+// The block owns its memory, so it can be moved to the thread that uses it.
+unsafe impl Send for NumaBlock {}
+
+impl NumaBlock {
+    /// Allocates without any placement policy.
+    pub fn alloc(size: usize) -> Result<Self, String> {
+        let ptr = os::alloc(size, None)?;
+        Ok(Self { ptr, size })
+    }
+
+    /// Allocates memory that must be backed by pages on the given numa node.
+    pub fn alloc_on_node(size: usize, numa_node: u32) -> Result<Self, String> {
+        let ptr = os::alloc(size, Some(numa_node))?;
+        Ok(Self { ptr, size })
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    /// Views the block as a slice of `T`.
+    ///
+    /// # Safety
+    ///
+    /// The memory starts zeroed or holds whatever was last written to it, so
+    /// `T` must be valid for any bit pattern, e.g. a plain integer.
+    pub unsafe fn as_mut_slice<T>(&mut self) -> &mut [T] {
+        std::slice::from_raw_parts_mut(self.ptr as *mut T, self.size / std::mem::size_of::<T>())
+    }
+
+    /// Writes to every page so the OS commits the memory before it is timed.
+    pub fn touch(&mut self) {
+        let page_size = os::page_size();
+        for offset in (0..self.size).step_by(page_size) {
+            unsafe { self.ptr.add(offset).write_volatile(0) };
+        }
+    }
+
+    /// The numa node each page of the block currently resides on, or `None`
+    /// if the platform can't report it. Pages that have not been touched yet
+    /// have a negative status.
+    pub fn page_nodes(&self) -> Option<Vec<i32>> {
+        os::page_nodes(self.ptr, self.size)
+    }
+
+    /// Checks that every resident page is on the expected numa node.
+    pub fn verify_node(&self, numa_node: u32) -> Result<(), String> {
+        let Some(nodes) = self.page_nodes() else {
+            return Ok(());
+        };
+
+        let misplaced = nodes
+            .iter()
+            .filter(|&&n| n >= 0 && n as u32 != numa_node)
+            .count();
+
+        if misplaced > 0 {
+            return Err(format!(
+                "{} of {} pages are not on numa node {}",
+                misplaced,
+                nodes.len(),
+                numa_node
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for NumaBlock {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            os::free(self.ptr, self.size);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod os {
+    use std::os::raw::{c_long, c_ulong, c_void};
+    use std::ptr::null_mut;
+
+    // from linux/mempolicy.h
+    const MPOL_BIND: c_long = 2;
+    const MPOL_MF_STRICT: c_ulong = 1 << 0;
+    const MPOL_MF_MOVE: c_ulong = 1 << 1;
+    const MAX_NODES: usize = 1024;
+
+    pub fn page_size() -> usize {
+        unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+    }
+
+    pub fn alloc(size: usize, numa_node: Option<u32>) -> Result<*mut u8, String> {
+        let ptr = unsafe {
+            libc::mmap(
+                null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+
+        if ptr == libc::MAP_FAILED {
+            return Err(format!(
+                "mmap of {} bytes failed: {}",
+                size,
+                std::io::Error::last_os_error()
+            ));
+        }
+
+        if let Some(numa_node) = numa_node {
+            if let Err(e) = bind(ptr, size, numa_node) {
+                free(ptr as *mut u8, size);
+                return Err(e);
+            }
+        }
+
+        Ok(ptr as *mut u8)
+    }
+
+    /// Binds an address range to a single numa node with `mbind`.
+    pub fn bind(ptr: *mut c_void, size: usize, numa_node: u32) -> Result<(), String> {
+        let bits = c_ulong::BITS as usize;
+        let node = numa_node as usize;
+        if node >= MAX_NODES {
+            return Err(format!("numa node {numa_node} out of range"));
+        }
+
+        let mut node_mask = [0 as c_ulong; MAX_NODES / c_ulong::BITS as usize];
+        node_mask[node / bits] |= 1 << (node % bits);
+
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_mbind,
+                ptr,
+                size as c_ulong,
+                MPOL_BIND,
+                node_mask.as_ptr(),
+                (MAX_NODES + 1) as c_ulong,
+                MPOL_MF_STRICT | MPOL_MF_MOVE,
+            )
+        };
+
+        if result != 0 {
+            return Err(format!(
+                "mbind to numa node {} failed: {}",
+                numa_node,
+                std::io::Error::last_os_error()
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn free(ptr: *mut u8, size: usize) {
+        unsafe { libc::munmap(ptr as *mut c_void, size) };
+    }
+
+    /// Uses `move_pages` without target nodes, which only reports where
+    /// each page currently lives. Falls back to asking `get_mempolicy`
+    /// page by page if `move_pages` is not permitted.
+    pub fn page_nodes(ptr: *mut u8, size: usize) -> Option<Vec<i32>> {
+        let page_size = page_size();
+        let pages: Vec<*mut c_void> = (0..size)
+            .step_by(page_size)
+            .map(|offset| unsafe { ptr.add(offset) } as *mut c_void)
+            .collect();
+        let mut status = vec![0i32; pages.len()];
+
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_move_pages,
+                0,
+                pages.len() as c_ulong,
+                pages.as_ptr(),
+                null_mut::<i32>(),
+                status.as_mut_ptr(),
+                0,
+            )
+        };
+
+        if result != 0 {
+            return pages
+                .iter()
+                .map(|&page| node_of_address(page as *const u8).map(|n| n as i32))
+                .collect();
+        }
+
+        Some(status)
+    }
+
+    /// The numa node of the page holding `ptr`, via `get_mempolicy`.
+    fn node_of_address(ptr: *const u8) -> Option<u32> {
+        const MPOL_F_NODE: c_ulong = 1 << 0;
+        const MPOL_F_ADDR: c_ulong = 1 << 1;
+
+        let mut node: i32 = -1;
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_get_mempolicy,
+                &mut node as *mut i32,
+                null_mut::<c_ulong>(),
+                0 as c_ulong,
+                ptr,
+                MPOL_F_NODE | MPOL_F_ADDR,
+            )
+        };
+
+        (result == 0 && node >= 0).then_some(node as u32)
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod os {
+    use std::ptr::null_mut;
+    use winapi::um::memoryapi::{VirtualAlloc, VirtualAllocExNuma, VirtualFree};
+    use winapi::um::processthreadsapi::GetCurrentProcess;
+    use winapi::um::sysinfoapi::{GetSystemInfo, SYSTEM_INFO};
+    use winapi::um::winnt::{MEM_COMMIT, MEM_RELEASE, MEM_RESERVE, PAGE_READWRITE};
+
+    use crate::perf_info::get_last_error_message;
+
+    pub fn page_size() -> usize {
+        let mut system_info: SYSTEM_INFO = unsafe { std::mem::zeroed() };
+        unsafe { GetSystemInfo(&mut system_info) };
+        system_info.dwPageSize as usize
+    }
+
+    pub fn alloc(size: usize, numa_node: Option<u32>) -> Result<*mut u8, String> {
+        let ptr = match numa_node {
+            None => unsafe {
+                VirtualAlloc(null_mut(), size, MEM_COMMIT | MEM_RESERVE, PAGE_READWRITE)
+            },
+            Some(numa_node) => unsafe {
+                VirtualAllocExNuma(
+                    GetCurrentProcess(),
+                    null_mut(),
+                    size,
+                    MEM_COMMIT | MEM_RESERVE,
+                    PAGE_READWRITE,
+                    numa_node,
+                )
+            },
+        };
+
+        if ptr.is_null() {
+            return Err(format!(
+                "Failed to allocate {} bytes: {}",
+                size,
+                get_last_error_message()
+            ));
+        }
+
+        Ok(ptr as *mut u8)
+    }
+
+    pub fn free(ptr: *mut u8, _size: usize) {
+        unsafe { VirtualFree(ptr.cast(), 0, MEM_RELEASE) };
+    }
+
+    // VirtualAllocExNuma only states a preference, and there is no cheap
+    // query for the node backing a page.
+    pub fn page_nodes(_ptr: *mut u8, _size: usize) -> Option<Vec<i32>> {
+        None
+    }
+}
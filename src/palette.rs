@@ -0,0 +1,194 @@
+// Colors, dashes and markers of chart series. A series gets its color from
+// its name alone, so it looks the same on every chart, and series that would
+// still look alike on one chart are told apart by dashes and markers.
+
+use plotters::style::colors::{BLUE, CYAN, GREEN, MAGENTA, RED};
+use plotters::style::RGBColor;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static BY_FAMILY: AtomicBool = AtomicBool::new(false);
+
+/// Colors each series by its family, the first word of its name, so that
+/// e.g. `scc u64` and `scc str` are shades of the same hue.
+pub fn set_by_family(by_family: bool) {
+    BY_FAMILY.store(by_family, Ordering::Relaxed);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dash {
+    Solid,
+    Dashed,
+    Dotted,
+    Long,
+}
+
+impl Dash {
+    /// Dash and gap length in pixels, `None` for a solid line.
+    pub fn pattern(self) -> Option<(i32, i32)> {
+        match self {
+            Dash::Solid => None,
+            Dash::Dashed => Some((8, 5)),
+            Dash::Dotted => Some((2, 4)),
+            Dash::Long => Some((16, 6)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Marker {
+    Circle,
+    Triangle,
+    Cross,
+    Ring,
+}
+
+/// How one series is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    pub color: RGBColor,
+    pub dash: Dash,
+    pub marker: Option<Marker>,
+}
+
+/// Colors for series without a color of their own.
+const PALETTE: [RGBColor; 10] = [
+    RGBColor(31, 119, 180),
+    RGBColor(255, 127, 14),
+    RGBColor(44, 160, 44),
+    RGBColor(214, 39, 40),
+    RGBColor(148, 103, 189),
+    RGBColor(140, 86, 75),
+    RGBColor(227, 119, 194),
+    RGBColor(127, 127, 127),
+    RGBColor(188, 189, 34),
+    RGBColor(23, 190, 207),
+];
+
+const DASHES: [Dash; 4] = [Dash::Solid, Dash::Dashed, Dash::Dotted, Dash::Long];
+const MARKERS: [Marker; 4] = [Marker::Circle, Marker::Triangle, Marker::Cross, Marker::Ring];
+
+/// Charts with more series than this mark every series.
+const MAX_UNMARKED: usize = 8;
+
+/// The colors the charts have always used for the maps and suites.
+fn known_color(name: &str) -> Option<RGBColor> {
+    let color = match name {
+        "bfix" | "ev" | "numa match" | "2" | "4 heaps" => GREEN,
+        "c#" | "sfix" | "normal" | "std alloc" | "atomic" | "64k" | "3" | "16 heaps" => RED,
+        "scc" | "hb" | "numa miss" | "numa aff" | "mutex" | "8mb" | "1" | "1 heap" => BLUE,
+        "nop" => CYAN,
+        "std" | "std-rwlock" | "core aff" | "4" => MAGENTA,
+        "evmap" => RGBColor(240, 140, 10),
+        "scc u64" => RGBColor(10, 10, 240),
+        "scc str" => RGBColor(10, 10, 180),
+        "bfix u64" => RGBColor(10, 240, 10),
+        "bfix str" => RGBColor(10, 180, 10),
+        _ => return None,
+    };
+    Some(color)
+}
+
+/// FNV-1a, which unlike the std hasher is the same on every run.
+fn hash(text: &str) -> u64 {
+    text.bytes()
+        .fold(0xcbf29ce484222325, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+fn palette_color(name: &str) -> RGBColor {
+    known_color(name).unwrap_or(PALETTE[(hash(name) % PALETTE.len() as u64) as usize])
+}
+
+/// The same hue, darker or lighter.
+fn shade(color: RGBColor, shade: u64) -> RGBColor {
+    let RGBColor(r, g, b) = color;
+    let scale = |c: u8, f: f64| (c as f64 * f).round() as u8;
+    let lighten = |c: u8| c + ((255 - c) as f64 * 0.4).round() as u8;
+    match shade % 3 {
+        0 => color,
+        1 => RGBColor(scale(r, 0.65), scale(g, 0.65), scale(b, 0.65)),
+        _ => RGBColor(lighten(r), lighten(g), lighten(b)),
+    }
+}
+
+/// The color of a series, on any chart.
+pub fn color(name: &str, by_family: bool) -> RGBColor {
+    if !by_family {
+        return palette_color(name);
+    }
+
+    let family = name.split_whitespace().next().unwrap_or(name);
+    let variant = name[family.len()..].trim();
+    let hue = palette_color(family);
+    if variant.is_empty() {
+        hue
+    } else {
+        shade(hue, hash(variant))
+    }
+}
+
+/// The style of each series of one chart.
+pub fn styles<'a>(names: impl IntoIterator<Item = &'a str>) -> HashMap<&'a str, Style> {
+    styles_with(names, BY_FAMILY.load(Ordering::Relaxed))
+}
+
+fn styles_with<'a>(names: impl IntoIterator<Item = &'a str>, by_family: bool) -> HashMap<&'a str, Style> {
+    let names: BTreeSet<&str> = names.into_iter().collect();
+    let marked = names.len() > MAX_UNMARKED;
+
+    // Series whose colors clash get the next dash, then the next marker
+    let mut clashes: HashMap<RGBColor, usize> = HashMap::new();
+    let mut styles = HashMap::new();
+    for (i, name) in names.into_iter().enumerate() {
+        let color = color(name, by_family);
+        let clash = clashes.entry(color).or_default();
+        let marker = if marked {
+            Some(MARKERS[i % MARKERS.len()])
+        } else if *clash >= DASHES.len() {
+            Some(MARKERS[(*clash / DASHES.len() - 1) % MARKERS.len()])
+        } else {
+            None
+        };
+        styles.insert(name, Style { color, dash: DASHES[*clash % DASHES.len()], marker });
+        *clash += 1;
+    }
+    styles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_styles_are_stable_and_distinct() {
+        let few = styles_with(["scc", "dashmap"], false);
+        let many = styles_with(["std", "ev str", "dashmap", "a", "b", "c", "d", "e", "f", "g", "scc"], false);
+        assert_eq!(few["scc"].color, BLUE);
+        assert_eq!(few["dashmap"].color, many["dashmap"].color);
+        assert_eq!(few["dashmap"].marker, None);
+
+        let looks: std::collections::HashSet<_> = many
+            .values()
+            .map(|s| (s.color, s.dash, s.marker))
+            .collect();
+        assert_eq!(looks.len(), many.len());
+        assert!(many.values().all(|s| s.marker.is_some()));
+    }
+
+    #[test]
+    fn test_family_colors_share_a_hue() {
+        assert_ne!(color("ev u64", false), color("ev str", false));
+        assert_eq!(color("scc", true), BLUE);
+
+        let styles = styles_with(["scc u64", "scc str", "scc"], true);
+        let hue = |c: RGBColor| {
+            let max = c.0.max(c.1).max(c.2) as f64;
+            (c.0 as f64 / max, c.1 as f64 / max, c.2 as f64 / max)
+        };
+        for name in ["scc u64", "scc str"] {
+            let (r, g, b) = hue(styles[name].color);
+            assert!(b == 1.0 && r < 0.5 && g < 0.5, "{} is not blue", name);
+        }
+        assert_ne!(styles["scc u64"], styles["scc str"]);
+    }
+}
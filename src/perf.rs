@@ -1,18 +1,271 @@
+use serde::{Deserialize, Serialize};
 use std::{sync::{Arc, Mutex}, time::Duration};
 
+use crate::allocator::Footprint;
+use crate::histogram::Percentiles;
+use crate::perf_counters::Counts;
+use crate::stats::{self, Summary};
+
 #[derive(Debug, Clone, Copy)] // Add these derives if needed for convenience
 pub struct Measurement<'a> {
     pub name : &'a str,
     pub latency: f64,
     pub thread_count: u64, 
+    /// The number of items in the map, for suites charted by map size
+    /// rather than by thread count.
+    pub items: Option<u64>,
+    /// The rate ops were scheduled at, for open loop runs.
+    pub target_rate: Option<f64>,
+    /// Per operation latency, for tests that time individual operations.
+    pub percentiles: Option<Percentiles>,
+    /// Wall clock time and rates, for tests that run threads side by side.
+    pub throughput: Option<Throughput>,
+    /// How much the value varied, for points measured over several trials.
+    pub spread: Option<Spread>,
+    /// Hardware events per operation, where the kernel allows counting them.
+    pub counters: Option<Counts>,
+    /// Heap use of the map, for runs that count allocations.
+    pub footprint: Option<Footprint>,
+}
+
+/// What one benchmark thread did after the start barrier was released.
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadResult {
+    pub elapsed: Duration,
+    pub ops: u64,
+    pub counters: Option<Counts>,
+}
+
+pub type ThreadResults = Arc<Mutex<Vec<ThreadResult>>>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Throughput {
+    /// Seconds from releasing the threads until the last one was joined.
+    pub wall_secs: f64,
+    /// Operations per second of all threads together, over the wall clock time.
+    pub ops_per_sec: f64,
+    /// Spread of each thread's own operations per second, which shows skew
+    /// between threads that the average latency hides.
+    pub thread_ops_per_sec_min: f64,
+    pub thread_ops_per_sec_mean: f64,
+    pub thread_ops_per_sec_max: f64,
+}
+
+/// The variation between the trials behind one measurement.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Spread {
+    pub trials: usize,
+    /// Trials left out as outliers, which the summaries don't include.
+    pub rejected: usize,
+    pub latency: Summary,
+    pub ops_per_sec: Option<Summary>,
+}
+
+/// How many times each point is measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrialConfig {
+    /// Runs thrown away before the measured ones, to warm caches and the heap.
+    pub warmup: usize,
+    pub trials: usize,
+    pub reject_outliers: bool,
+}
+
+impl Default for TrialConfig {
+    fn default() -> Self {
+        Self {
+            warmup: 0,
+            trials: 1,
+            reject_outliers: false,
+        }
+    }
+}
+
+impl TrialConfig {
+    /// Calls `trial` for the warmup runs and then for each measured trial,
+    /// and combines the trials into one measurement.
+    pub fn run<'a>(&self, mut trial: impl FnMut() -> Measurement<'a>) -> Measurement<'a> {
+        for _ in 0..self.warmup {
+            trial();
+        }
+
+        let results: Vec<Measurement<'a>> = (0..self.trials.max(1)).map(|_| trial()).collect();
+        let combined = Measurement::combine(&results, self.reject_outliers);
+        if let Some(spread) = &combined.spread {
+            println!("  {}", spread.summary());
+        }
+        combined
+    }
+}
+
+impl Spread {
+    pub fn summary(&self) -> String {
+        let l = &self.latency;
+        format!(
+            "{} trials ({} rejected): mean: {:.2} ns  median: {:.2} ns  stddev: {:.2} ns  95% ci: {:.2}..{:.2} ns",
+            self.trials, self.rejected, l.mean, l.median, l.stddev, l.ci_low, l.ci_high
+        )
+    }
 }
 
-pub fn calc_av_nanos(results: Arc<Mutex<Vec<Duration>>>, total_ops: u64) -> f64 {
-    let results = results.lock().unwrap();
-    let total_latency: u128 = results
-        .iter()
-        .map(|m| m.as_nanos())
-        .sum();
-    let average_duration = total_latency as f64 / total_ops as f64;
-    average_duration
+impl<'a> Measurement<'a> {
+    /// A point with only an average latency, for tests that time a whole
+    /// loop. The other fields can be filled in after.
+    pub fn new(name: &'a str, latency: f64, thread_count: u64) -> Self {
+        Self {
+            name,
+            latency,
+            thread_count,
+            items: None,
+            target_rate: None,
+            percentiles: None,
+            throughput: None,
+            spread: None,
+            counters: None,
+            footprint: None,
+        }
+    }
+
+    /// Where the point goes along a chart's x axis: the item count for
+    /// suites sized by it, otherwise the thread count.
+    pub fn x(&self) -> u64 {
+        self.items.unwrap_or(self.thread_count)
+    }
+
+    /// Builds a measurement from what each thread did and the wall clock time
+    /// of the whole run. The latency is the time spent in all the threads over
+    /// the operations they performed.
+    pub fn from_threads(name: &'a str, results: &ThreadResults, wall: Duration) -> Self {
+        let results = results.lock().unwrap();
+        let total_ops: u64 = results.iter().map(|r| r.ops).sum();
+        let total_latency: u128 = results.iter().map(|r| r.elapsed.as_nanos()).sum();
+
+        let thread_rates: Vec<f64> = results
+            .iter()
+            .map(|r| r.ops as f64 / r.elapsed.as_secs_f64().max(1e-9))
+            .collect();
+        let wall_secs = wall.as_secs_f64();
+
+        // Only report counters when every thread managed to count
+        let counters = results
+            .iter()
+            .map(|r| r.counters)
+            .collect::<Option<Vec<Counts>>>()
+            .and_then(|c| c.into_iter().reduce(|a, b| a.add(&b)))
+            .map(|c| c.per_op(total_ops));
+
+        Self {
+            counters,
+            throughput: Some(Throughput {
+                wall_secs,
+                ops_per_sec: total_ops as f64 / wall_secs.max(1e-9),
+                thread_ops_per_sec_min: thread_rates.iter().copied().fold(f64::INFINITY, f64::min),
+                thread_ops_per_sec_mean: thread_rates.iter().sum::<f64>() / thread_rates.len() as f64,
+                thread_ops_per_sec_max: thread_rates.iter().copied().fold(0.0, f64::max),
+            }),
+            ..Self::new(name, total_latency as f64 / total_ops as f64, results.len() as u64)
+        }
+    }
+
+    /// Combines trials of the same point. The latency and rates are the means
+    /// of the trials that are kept, and the percentiles and counters are
+    /// those of the trial with the median latency. Outliers are judged by latency.
+    pub fn combine(trials: &[Measurement<'a>], reject_outliers: bool) -> Self {
+        assert!(!trials.is_empty(), "no trials to combine");
+        if trials.len() == 1 {
+            return trials[0];
+        }
+
+        let latencies: Vec<f64> = trials.iter().map(|m| m.latency).collect();
+        let kept: Vec<&Measurement> = if reject_outliers {
+            stats::reject_outliers(&latencies).into_iter().map(|i| &trials[i]).collect()
+        } else {
+            trials.iter().collect()
+        };
+        let kept_latencies: Vec<f64> = kept.iter().map(|m| m.latency).collect();
+
+        let median_latency = stats::median(&kept_latencies);
+        let median_trial = kept
+            .iter()
+            .min_by(|a, b| {
+                let da = (a.latency - median_latency).abs();
+                let db = (b.latency - median_latency).abs();
+                da.total_cmp(&db)
+            })
+            .unwrap();
+
+        let throughputs: Option<Vec<Throughput>> = kept.iter().map(|m| m.throughput).collect();
+        let throughput = throughputs.as_ref().map(|t| {
+            let mean_of = |f: fn(&Throughput) -> f64| stats::mean(&t.iter().map(f).collect::<Vec<_>>());
+            Throughput {
+                wall_secs: mean_of(|t| t.wall_secs),
+                ops_per_sec: mean_of(|t| t.ops_per_sec),
+                thread_ops_per_sec_min: mean_of(|t| t.thread_ops_per_sec_min),
+                thread_ops_per_sec_mean: mean_of(|t| t.thread_ops_per_sec_mean),
+                thread_ops_per_sec_max: mean_of(|t| t.thread_ops_per_sec_max),
+            }
+        });
+
+        let latency = Summary::new(&kept_latencies);
+        Self {
+            name: trials[0].name,
+            latency: latency.mean,
+            thread_count: trials[0].thread_count,
+            items: trials[0].items,
+            target_rate: trials[0].target_rate,
+            percentiles: median_trial.percentiles,
+            counters: median_trial.counters,
+            footprint: median_trial.footprint,
+            throughput,
+            spread: Some(Spread {
+                trials: trials.len(),
+                rejected: trials.len() - kept.len(),
+                latency,
+                ops_per_sec: throughputs.map(|t| {
+                    Summary::new(&t.iter().map(|t| t.ops_per_sec).collect::<Vec<_>>())
+                }),
+            }),
+        }
+    }
+
+    /// One line summary printed after each run.
+    pub fn summary(&self) -> String {
+        let mut line = format!("avg: {:8.2} ns", self.latency);
+        if let Some(t) = &self.throughput {
+            line += &format!("  {:8.2} Mops/s", t.ops_per_sec / 1e6);
+        }
+        if let Some(p) = &self.percentiles {
+            line += &format!(
+                "  p50: {:6} ns  p99: {:6} ns  p99.9: {:7} ns  max: {:9} ns",
+                p.p50, p.p99, p.p999, p.max
+            );
+        }
+        if let Some(c) = &self.counters {
+            line += &c.summary();
+        }
+        if let Some(f) = &self.footprint {
+            line += &format!("  {}", f.summary());
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_combine_rejects_by_trial() {
+        let trials: Vec<Measurement> = [10.0, 11.0, 40.0, 10.0, 9.5, 10.5]
+            .iter()
+            .map(|&latency| Measurement::new("scc", latency, 4))
+            .collect();
+
+        let combined = Measurement::combine(&trials, true);
+        let spread = combined.spread.unwrap();
+        assert_eq!((spread.trials, spread.rejected), (6, 1));
+        assert_eq!(combined.latency, 10.2);
+
+        let combined = Measurement::combine(&trials, false);
+        assert_eq!(combined.spread.unwrap().rejected, 0);
+    }
 }
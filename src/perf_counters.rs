@@ -0,0 +1,394 @@
+use serde::{Deserialize, Serialize};
+
+/// Hardware event totals for a timed region, or the per operation averages
+/// once divided by the op count. Events the cpu or kernel doesn't support are
+/// `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Counts {
+    pub cycles: Option<f64>,
+    pub instructions: Option<f64>,
+    pub l1d_misses: Option<f64>,
+    pub llc_misses: Option<f64>,
+    pub dtlb_misses: Option<f64>,
+    /// Loads served by another numa node.
+    pub remote_accesses: Option<f64>,
+}
+
+impl Counts {
+    fn fields(&mut self) -> [&mut Option<f64>; 6] {
+        [
+            &mut self.cycles,
+            &mut self.instructions,
+            &mut self.l1d_misses,
+            &mut self.llc_misses,
+            &mut self.dtlb_misses,
+            &mut self.remote_accesses,
+        ]
+    }
+
+    /// Adds the counts of another thread. An event missing from either is
+    /// missing from the total.
+    pub fn add(&self, other: &Counts) -> Counts {
+        let mut sum = *self;
+        let mut other = *other;
+        for (a, b) in sum.fields().into_iter().zip(other.fields()) {
+            *a = a.zip(*b).map(|(a, b)| a + b);
+        }
+        sum
+    }
+
+    pub fn per_op(&self, ops: u64) -> Counts {
+        let mut counts = *self;
+        for value in counts.fields() {
+            *value = value.map(|v| v / ops.max(1) as f64);
+        }
+        counts
+    }
+
+    pub fn summary(&self) -> String {
+        let mut line = String::new();
+        let mut counts = *self;
+        let names = ["cycles", "instr", "l1d miss", "llc miss", "dtlb miss", "remote"];
+        for (name, value) in names.iter().zip(counts.fields()) {
+            if let Some(value) = value {
+                line += &format!("  {}: {:.2}", name, value);
+            }
+        }
+        line
+    }
+}
+
+/// The counters of the calling thread. Opening them fails quietly, after one
+/// warning, when the kernel denies access or the platform has no support, and
+/// `stop` then returns `None`.
+pub struct PerfCounters {
+    inner: Option<os::Counters>,
+}
+
+impl PerfCounters {
+    pub fn open() -> Self {
+        let inner = match os::Counters::open() {
+            Ok(counters) => Some(counters),
+            Err(e) => {
+                warn_once(&e);
+                None
+            }
+        };
+        Self { inner }
+    }
+
+    /// Resets and starts counting.
+    pub fn start(&self) {
+        if let Some(counters) = &self.inner {
+            counters.start();
+        }
+    }
+
+    /// Stops counting and reads the totals since `start`.
+    pub fn stop(&self) -> Option<Counts> {
+        self.inner.as_ref().map(|counters| counters.stop())
+    }
+}
+
+fn warn_once(error: &str) {
+    static WARNED: std::sync::Once = std::sync::Once::new();
+    WARNED.call_once(|| eprintln!("Hardware counters unavailable: {}", error));
+}
+
+#[cfg(target_os = "linux")]
+mod os {
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    use super::Counts;
+
+    const PERF_TYPE_HARDWARE: u32 = 0;
+    const PERF_TYPE_HW_CACHE: u32 = 3;
+
+    const PERF_COUNT_HW_CPU_CYCLES: u64 = 0;
+    const PERF_COUNT_HW_INSTRUCTIONS: u64 = 1;
+
+    const PERF_COUNT_HW_CACHE_L1D: u64 = 0;
+    const PERF_COUNT_HW_CACHE_LL: u64 = 2;
+    const PERF_COUNT_HW_CACHE_DTLB: u64 = 3;
+    const PERF_COUNT_HW_CACHE_NODE: u64 = 6;
+    const PERF_COUNT_HW_CACHE_OP_READ: u64 = 0;
+    const PERF_COUNT_HW_CACHE_RESULT_MISS: u64 = 1;
+
+    const PERF_FORMAT_TOTAL_TIME_ENABLED: u64 = 1 << 0;
+    const PERF_FORMAT_TOTAL_TIME_RUNNING: u64 = 1 << 1;
+    const PERF_FORMAT_GROUP: u64 = 1 << 3;
+
+    const FLAG_DISABLED: u64 = 1 << 0;
+    const FLAG_EXCLUDE_KERNEL: u64 = 1 << 5;
+    const FLAG_EXCLUDE_HV: u64 = 1 << 6;
+
+    const PERF_EVENT_IOC_ENABLE: libc::c_ulong = 0x2400;
+    const PERF_EVENT_IOC_DISABLE: libc::c_ulong = 0x2401;
+    const PERF_EVENT_IOC_RESET: libc::c_ulong = 0x2403;
+    const PERF_IOC_FLAG_GROUP: libc::c_ulong = 1;
+    const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 1 << 3;
+
+    /// The first version of `perf_event_attr`, which every kernel accepts.
+    #[repr(C)]
+    #[derive(Default)]
+    struct PerfEventAttr {
+        type_: u32,
+        size: u32,
+        config: u64,
+        sample_period: u64,
+        sample_type: u64,
+        read_format: u64,
+        flags: u64,
+        wakeup_events: u32,
+        bp_type: u32,
+        config1: u64,
+    }
+
+    const fn cache_miss(cache: u64) -> u64 {
+        cache | (PERF_COUNT_HW_CACHE_OP_READ << 8) | (PERF_COUNT_HW_CACHE_RESULT_MISS << 16)
+    }
+
+    #[derive(Clone, Copy)]
+    enum Event {
+        Cycles,
+        Instructions,
+        L1dMisses,
+        LlcMisses,
+        DtlbMisses,
+        RemoteAccesses,
+    }
+
+    impl Event {
+        fn attr(&self) -> (u32, u64) {
+            match self {
+                Event::Cycles => (PERF_TYPE_HARDWARE, PERF_COUNT_HW_CPU_CYCLES),
+                Event::Instructions => (PERF_TYPE_HARDWARE, PERF_COUNT_HW_INSTRUCTIONS),
+                Event::L1dMisses => (PERF_TYPE_HW_CACHE, cache_miss(PERF_COUNT_HW_CACHE_L1D)),
+                Event::LlcMisses => (PERF_TYPE_HW_CACHE, cache_miss(PERF_COUNT_HW_CACHE_LL)),
+                Event::DtlbMisses => (PERF_TYPE_HW_CACHE, cache_miss(PERF_COUNT_HW_CACHE_DTLB)),
+                Event::RemoteAccesses => (PERF_TYPE_HW_CACHE, cache_miss(PERF_COUNT_HW_CACHE_NODE)),
+            }
+        }
+
+        fn slot<'a>(&self, counts: &'a mut Counts) -> &'a mut Option<f64> {
+            match self {
+                Event::Cycles => &mut counts.cycles,
+                Event::Instructions => &mut counts.instructions,
+                Event::L1dMisses => &mut counts.l1d_misses,
+                Event::LlcMisses => &mut counts.llc_misses,
+                Event::DtlbMisses => &mut counts.dtlb_misses,
+                Event::RemoteAccesses => &mut counts.remote_accesses,
+            }
+        }
+    }
+
+    /// The core and memory events go in separate groups. A group is only
+    /// counted when all of it fits on the pmu at once, and six events can be
+    /// more than a cpu has counters for.
+    const GROUPS: [&[Event]; 2] = [
+        &[Event::Cycles, Event::Instructions],
+        &[Event::L1dMisses, Event::LlcMisses, Event::DtlbMisses, Event::RemoteAccesses],
+    ];
+
+    struct Group {
+        leader: File,
+        events: Vec<Event>,
+        _members: Vec<File>,
+    }
+
+    pub struct Counters {
+        groups: Vec<Group>,
+    }
+
+    fn open_event(event: Event, group_fd: i32) -> std::io::Result<File> {
+        let (type_, config) = event.attr();
+        let mut attr = PerfEventAttr {
+            type_,
+            size: std::mem::size_of::<PerfEventAttr>() as u32,
+            config,
+            read_format: PERF_FORMAT_GROUP | PERF_FORMAT_TOTAL_TIME_ENABLED | PERF_FORMAT_TOTAL_TIME_RUNNING,
+            flags: FLAG_EXCLUDE_KERNEL | FLAG_EXCLUDE_HV,
+            ..Default::default()
+        };
+        if group_fd == -1 {
+            attr.flags |= FLAG_DISABLED;
+        }
+
+        // Count this thread on whichever cpu it runs
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_perf_event_open,
+                &attr as *const PerfEventAttr,
+                0,
+                -1,
+                group_fd,
+                PERF_FLAG_FD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(unsafe { File::from_raw_fd(fd as i32) })
+    }
+
+    impl Group {
+        /// Opens the events the cpu has, or fails if it has none of them.
+        fn open(events: &[Event]) -> std::io::Result<Self> {
+            let mut leader: Option<(File, Event)> = None;
+            let mut members = Vec::new();
+            let mut opened = Vec::new();
+            let mut first_error = None;
+
+            for &event in events {
+                let group_fd = leader.as_ref().map_or(-1, |(f, _)| f.as_raw_fd());
+                match open_event(event, group_fd) {
+                    Ok(file) => {
+                        opened.push(event);
+                        match leader {
+                            None => leader = Some((file, event)),
+                            Some(_) => members.push(file),
+                        }
+                    }
+                    Err(e) => {
+                        first_error.get_or_insert(e);
+                    }
+                }
+            }
+
+            match leader {
+                Some((leader, _)) => Ok(Group {
+                    leader,
+                    events: opened,
+                    _members: members,
+                }),
+                None => Err(first_error.unwrap()),
+            }
+        }
+
+        fn ioctl(&self, request: libc::c_ulong) {
+            unsafe { libc::ioctl(self.leader.as_raw_fd(), request, PERF_IOC_FLAG_GROUP) };
+        }
+
+        /// Reads the group, scaling the counts up if the kernel had to share
+        /// the counters with other groups.
+        fn read(&self, counts: &mut Counts) {
+            let mut buf = vec![0u8; 8 * (3 + self.events.len())];
+            if (&self.leader).read_exact(&mut buf).is_err() {
+                return;
+            }
+
+            let values: Vec<u64> = buf
+                .chunks_exact(8)
+                .map(|c| u64::from_ne_bytes(c.try_into().unwrap()))
+                .collect();
+            let (enabled, running) = (values[1], values[2]);
+            if running == 0 {
+                return;
+            }
+
+            let scale = enabled as f64 / running as f64;
+            for (event, &value) in self.events.iter().zip(&values[3..]) {
+                *event.slot(counts) = Some(value as f64 * scale);
+            }
+        }
+    }
+
+    impl Counters {
+        pub fn open() -> Result<Self, String> {
+            let mut groups = Vec::new();
+            let mut errors = Vec::new();
+            for events in GROUPS {
+                match Group::open(events) {
+                    Ok(group) => groups.push(group),
+                    Err(e) => errors.push(e),
+                }
+            }
+
+            if groups.is_empty() {
+                let e = &errors[0];
+                return Err(match e.raw_os_error() {
+                    Some(libc::EACCES) | Some(libc::EPERM) => {
+                        format!("{} (see /proc/sys/kernel/perf_event_paranoid)", e)
+                    }
+                    _ => e.to_string(),
+                });
+            }
+            Ok(Self { groups })
+        }
+
+        pub fn start(&self) {
+            for group in &self.groups {
+                group.ioctl(PERF_EVENT_IOC_RESET);
+                group.ioctl(PERF_EVENT_IOC_ENABLE);
+            }
+        }
+
+        pub fn stop(&self) -> Counts {
+            for group in &self.groups {
+                group.ioctl(PERF_EVENT_IOC_DISABLE);
+            }
+            let mut counts = Counts::default();
+            for group in &self.groups {
+                group.read(&mut counts);
+            }
+            counts
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod os {
+    use super::Counts;
+
+    pub struct Counters;
+
+    impl Counters {
+        pub fn open() -> Result<Self, String> {
+            Err("only supported on Linux".to_string())
+        }
+
+        pub fn start(&self) {}
+
+        pub fn stop(&self) -> Counts {
+            Counts::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_add_and_per_op() {
+        let a = Counts {
+            cycles: Some(100.0),
+            instructions: Some(50.0),
+            remote_accesses: Some(4.0),
+            ..Default::default()
+        };
+        let b = Counts {
+            cycles: Some(300.0),
+            instructions: Some(150.0),
+            ..Default::default()
+        };
+
+        let per_op = a.add(&b).per_op(100);
+        assert_eq!(per_op.cycles, Some(4.0));
+        assert_eq!(per_op.instructions, Some(2.0));
+        assert_eq!(per_op.remote_accesses, None);
+    }
+
+    #[test]
+    fn test_open_degrades_gracefully() {
+        // Whether or not the kernel allows it, counting must not fail
+        let counters = PerfCounters::open();
+        counters.start();
+        std::hint::black_box((0..1000u64).sum::<u64>());
+        if let Some(counts) = counters.stop() {
+            assert!(counts.cycles.unwrap_or(0.0) >= 0.0);
+        }
+    }
+}
@@ -1,23 +1,238 @@
-use std::ffi::{c_void, OsString};
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "windows")]
+use std::ffi::OsString;
 use std::fs::File;
 use std::io::Write;
-use std::mem::{size_of, transmute};
+use std::path::Path;
+#[cfg(target_os = "windows")]
+use std::mem::size_of;
+#[cfg(target_os = "windows")]
 use std::os::windows::ffi::OsStringExt;
-use std::ptr::{self, null_mut, NonNull};
+#[cfg(target_os = "windows")]
+use std::ptr;
 
+#[cfg(target_os = "windows")]
 use winapi::shared::minwindef::DWORD;
+#[cfg(target_os = "windows")]
 use winapi::shared::winerror::ERROR_INSUFFICIENT_BUFFER;
+#[cfg(target_os = "windows")]
 use winapi::um::errhandlingapi::GetLastError;
+#[cfg(target_os = "windows")]
 use winapi::um::winbase::{FormatMessageW, FORMAT_MESSAGE_FROM_SYSTEM, FORMAT_MESSAGE_IGNORE_INSERTS};
+#[cfg(target_os = "windows")]
 use winapi::um::winnt::{
-    RelationAll, RelationCache, RelationNumaNode, RelationProcessorCore, RelationProcessorPackage, GROUP_AFFINITY, SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX
+    RelationAll, RelationCache, RelationGroup, RelationNumaNode, RelationProcessorCore,
+    RelationProcessorPackage, SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX,
 };
 
-use winapi::um::winnt::{
-    CACHE_RELATIONSHIP, GROUP_RELATIONSHIP, NUMA_NODE_RELATIONSHIP, PROCESSOR_GROUP_INFO,
-    PROCESSOR_RELATIONSHIP,
-};
+/// Description of the machine a benchmark ran on.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MachineInfo {
+    pub os: String,
+    pub cpu_model: String,
+    pub logical_cpus: usize,
+    pub packages: Vec<PackageInfo>,
+    pub numa_nodes: Vec<NumaNodeInfo>,
+    pub caches: Vec<CacheInfo>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PackageInfo {
+    pub id: u32,
+    pub cores: Vec<PhysicalCore>,
+}
+
+/// A physical core and the logical processors (SMT threads) on it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PhysicalCore {
+    pub id: u32,
+    pub cpus: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NumaNodeInfo {
+    pub id: u32,
+    pub cpus: Vec<usize>,
+    pub memory_bytes: Option<u64>,
+    // relative access cost to each node, as reported by the firmware (SLIT)
+    pub distances: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheInfo {
+    pub level: u32,
+    pub kind: String,
+    pub size_bytes: u64,
+    pub line_size: u32,
+    pub ways: Option<u32>,
+    pub shared_cpus: Vec<usize>,
+}
+
+impl MachineInfo {
+    pub fn num_cores(&self) -> usize {
+        self.packages.iter().map(|p| p.cores.len()).sum()
+    }
+
+    pub fn smt(&self) -> bool {
+        self.packages
+            .iter()
+            .flat_map(|p| p.cores.iter())
+            .any(|c| c.cpus.len() > 1)
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn machine_info() -> Result<MachineInfo, String> {
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+    crate::sysfs::SysFs::default().machine_info(&cpuinfo)
+}
+
+#[cfg(target_os = "windows")]
+pub fn machine_info() -> Result<MachineInfo, String> {
+    use crate::perf_mem::{for_each_processor_info, get_logical_processor_info};
+
+    let mask_cpus = |group: u16, mask: usize| -> Vec<usize> {
+        (0..64)
+            .filter(|i| mask & (1 << i) != 0)
+            .map(|i| group as usize * 64 + i)
+            .collect()
+    };
+
+    let mut info = MachineInfo {
+        os: std::env::consts::OS.to_string(),
+        cpu_model: std::env::var("PROCESSOR_IDENTIFIER").unwrap_or_default(),
+        ..Default::default()
+    };
+    let mut cores = Vec::new();
+
+    let buffer = get_logical_processor_info(RelationAll)?;
+    for_each_processor_info(&buffer, |p_buffer_ref| match p_buffer_ref.Relationship {
+        RelationProcessorCore => {
+            let core = unsafe { p_buffer_ref.u.Processor() };
+            let g = &core.GroupMask[0];
+            cores.push(mask_cpus(g.Group, g.Mask));
+        }
+        RelationProcessorPackage => {
+            let package = unsafe { p_buffer_ref.u.Processor() };
+            let g = &package.GroupMask[0];
+            let cpus = mask_cpus(g.Group, g.Mask);
+            info.packages.push(PackageInfo {
+                id: info.packages.len() as u32,
+                cores: cpus.iter().map(|&c| PhysicalCore { id: c as u32, cpus: vec![c] }).collect(),
+            });
+        }
+        RelationNumaNode => {
+            let node = unsafe { p_buffer_ref.u.NumaNode() };
+            info.numa_nodes.push(NumaNodeInfo {
+                id: node.NodeNumber,
+                cpus: mask_cpus(node.GroupMask.Group, node.GroupMask.Mask),
+                ..Default::default()
+            });
+        }
+        RelationCache => {
+            let cache = unsafe { p_buffer_ref.u.Cache() };
+            let cachetype_list = ["Unified", "Instruction", "Data", "Trace"];
+            info.caches.push(CacheInfo {
+                level: cache.Level as u32,
+                kind: cachetype_list.get(cache.Type as usize).unwrap_or(&"").to_string(),
+                size_bytes: cache.CacheSize as u64,
+                line_size: cache.LineSize as u32,
+                ways: (cache.Associativity != 0xff).then_some(cache.Associativity as u32),
+                shared_cpus: mask_cpus(cache.GroupMask.Group, cache.GroupMask.Mask),
+            });
+        }
+        _ => {}
+    });
 
+    // replace the per processor placeholders with the physical cores
+    for package in info.packages.iter_mut() {
+        let package_cpus: Vec<usize> = package.cores.iter().flat_map(|c| c.cpus.clone()).collect();
+        package.cores = cores
+            .iter()
+            .filter(|cpus| cpus.iter().all(|c| package_cpus.contains(c)))
+            .enumerate()
+            .map(|(id, cpus)| PhysicalCore { id: id as u32, cpus: cpus.clone() })
+            .collect();
+    }
+
+    info.logical_cpus = cores.iter().map(|c| c.len()).sum();
+    Ok(info)
+}
+
+/// Writes the machine description as JSON, for attaching to results.
+fn write_machine_info_json(path: &Path, info: &MachineInfo) {
+    let file = File::create(path).expect("Failed to create file");
+    serde_json::to_writer_pretty(file, info).expect("Failed to write machine info");
+}
+
+#[cfg(target_os = "linux")]
+pub fn write_cpu_info(out_dir: &Path) {
+    let info = match machine_info() {
+        Ok(info) => info,
+        Err(e) => {
+            println!("Failed to read machine info: {e}");
+            return;
+        }
+    };
+
+    write_machine_info_json(&out_dir.join("processor.info.json"), &info);
+
+    let mut file = File::create(out_dir.join("processor.info.txt")).expect("Failed to create file");
+    write_machine_info_text(&mut file, &info).expect("Failed to write machine info");
+}
+
+#[cfg(target_os = "linux")]
+fn write_machine_info_text(file: &mut File, info: &MachineInfo) -> std::io::Result<()> {
+    writeln!(file, "{} ({})", info.cpu_model, info.os)?;
+    writeln!(
+        file,
+        "{} packages, {} cores, {} logical processors, SMT: {}",
+        info.packages.len(),
+        info.num_cores(),
+        info.logical_cpus,
+        yesno(info.smt())
+    )?;
+    writeln!(file)?;
+
+    for package in &info.packages {
+        writeln!(file, "ProcessorPackage #{}", package.id)?;
+        for core in &package.cores {
+            writeln!(file, " Core #{} = {}", core.id, format_cpus(&core.cpus))?;
+        }
+        writeln!(file)?;
+    }
+
+    for node in &info.numa_nodes {
+        writeln!(file, "NumaNode #{}", node.id)?;
+        writeln!(file, " Cpus = {}", format_cpus(&node.cpus))?;
+        if let Some(memory) = node.memory_bytes {
+            writeln!(file, " Memory = {}MB", memory / (1024 * 1024))?;
+        }
+        writeln!(file, " Distances = {:?}", node.distances)?;
+        writeln!(file)?;
+    }
+
+    for cache in &info.caches {
+        writeln!(file, "Cache L{} {}", cache.level, cache.kind)?;
+        match cache.ways {
+            Some(ways) => writeln!(file, " Assoc: {}", ways)?,
+            None => writeln!(file, " Assoc: full")?,
+        }
+        writeln!(file, " Line Size = {}B", cache.line_size)?;
+        writeln!(file, " Cache Size = {}KB", cache.size_bytes / 1024)?;
+        writeln!(file, " Cpus = {}", format_cpus(&cache.shared_cpus))?;
+        writeln!(file)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn format_cpus(cpus: &[usize]) -> String {
+    cpus.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(",")
+}
+
+#[cfg(target_os = "windows")]
 pub fn get_last_error_message() -> String {
     unsafe {
         let error_code = GetLastError();
@@ -52,6 +267,7 @@ fn yesno(b: bool) -> &'static str {
     }
 }
 
+#[cfg(target_os = "windows")]
 fn format_bitmap(mask: usize) -> String {  // Assuming ULONG_PTR is usize
     let mut result = String::new();
     for i in (0..size_of::<usize>() * 8).rev() {
@@ -60,6 +276,7 @@ fn format_bitmap(mask: usize) -> String {  // Assuming ULONG_PTR is usize
     result
 }
 
+#[cfg(target_os = "windows")]
 #[link(name = "kernel32")]
 extern "system" {
     pub fn GetLogicalProcessorInformationEx(
@@ -69,9 +286,14 @@ extern "system" {
     ) -> u32; // Returns BOOL (which is an alias for i32)
 }
 
-pub fn write_cpu_info() {
+#[cfg(target_os = "windows")]
+pub fn write_cpu_info(out_dir: &Path) {
+    match machine_info() {
+        Ok(info) => write_machine_info_json(&out_dir.join("processor.info.json"), &info),
+        Err(e) => println!("Failed to read machine info: {e}"),
+    }
 
-    let mut file = File::create("processor.info.txt").expect("Failed to create file");
+    let mut file = File::create(out_dir.join("processor.info.txt")).expect("Failed to create file");
 
 
     let mut buffer = vec![0u8; 1];
@@ -1,18 +1,26 @@
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::ops::Range;
 use std::sync::Mutex;
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc, Barrier,
 };
 use std::thread;
-use std::time::Duration;
-use std::time::Instant;
-
-use crate::perf::calc_av_nanos;
-use crate::perf::Measurement;
+use std::time::{Duration, Instant};
+
+use crate::allocator::{self, Footprint};
+use crate::affinity::{set_thread_affinity, Pinning};
+use crate::histogram::{timer_overhead, Histogram};
+use crate::key_sampler::KeySampler;
+use crate::perf::{Measurement, ThreadResult, ThreadResults};
+use crate::perf_counters::PerfCounters;
+use crate::perf_mem::get_core_info;
+use crate::scenario::KeyDistribution;
+use crate::timeline::{ProgressCounter, Sampler, Timeline, TimelineRecorder, PUBLISH_EVERY};
 
 /// A collection that can be benchmarked by bustle.
 ///
@@ -21,8 +29,15 @@ use crate::perf::Measurement;
 /// loop of the benchmark.
 pub trait Collection: Send + Sync + 'static {
     type Handle: CollectionHandle;
+    fn with_capacity(capacity: usize) -> Self;
     fn pin(&self) -> Self::Handle;
     fn prefill_complete(&self);
+
+    /// Number of items in the collection, if it can tell. Used to check the
+    /// final size in verify mode.
+    fn len(&self) -> Option<usize> {
+        None
+    }
 }
 
 /// A handle to a key-value collection.
@@ -30,6 +45,12 @@ pub trait Collection: Send + Sync + 'static {
 /// Note that for all these methods, the benchmarker does not dictate what the values are. Feel
 /// free to use the same value for all operations, or use distinct ones and check that your
 /// retrievals indeed return the right results.
+///
+/// `get`, `insert`, `remove` and `update` return whether they succeeded, and
+/// a false is counted as a failed op. `upsert`, `get_or_insert` and `compute`
+/// can't fail, they return true if the key was already there. Each must be
+/// a single atomic operation of the map; maps that can only compose them
+/// from other calls leave them out, see `Adapter::unsupported`.
 pub trait CollectionHandle {
     type Key: Clone + Send + Sync + FromU64;
 
@@ -37,6 +58,13 @@ pub trait CollectionHandle {
     fn insert(&self, key: Self::Key) -> bool;
     fn remove(&self, key: &Self::Key) -> bool;
     fn update(&self, key: &Self::Key) -> bool;
+
+    /// Inserts the key or replaces its value.
+    fn upsert(&self, key: Self::Key) -> bool;
+    /// Inserts the key with a value made only when it is missing.
+    fn get_or_insert(&self, key: Self::Key) -> bool;
+    /// Modifies the key's value in place, inserting it first if missing.
+    fn compute(&self, key: Self::Key) -> bool;
 }
 
 pub trait ValueModifier {
@@ -102,9 +130,12 @@ where
         self.allocated.store(0, Ordering::Relaxed);
     }
 
-    pub fn random(&self, i: usize) -> TK {
-        let allocated = self.allocated.load(Ordering::Relaxed);
-        self.keys[i % allocated].clone()
+    pub fn get(&self, index: usize) -> &TK {
+        &self.keys[index]
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
     }
 
     // too slow
@@ -114,8 +145,13 @@ where
     // }
 
     pub fn alloc_n(&self, count: usize) -> &[TK] {
+        &self.keys[self.alloc_range(count)]
+    }
+
+    /// Allocates `count` keys, returning their indexes.
+    pub fn alloc_range(&self, count: usize) -> Range<usize> {
         let i = self.allocated.fetch_add(count, Ordering::Relaxed);
-        &self.keys[i..(i + count)]
+        i..(i + count)
     }
 }
 
@@ -126,15 +162,28 @@ pub enum Operation {
     Remove,
     Update,
     Upsert,
+    GetOrInsert,
+    Compute,
 }
 
-#[derive(Clone, Copy, Debug)]
+impl Operation {
+    /// Operations that leave the key in the map whether it was there or not.
+    pub fn writes(self) -> bool {
+        matches!(self, Operation::Upsert | Operation::GetOrInsert | Operation::Compute)
+    }
+}
+
+/// Percentage of each operation type, adding up to 100.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Mix {
     pub read: usize,
     pub insert: usize,
     pub remove: usize,
     pub update: usize,
     pub upsert: usize,
+    pub get_or_insert: usize,
+    pub compute: usize,
 }
 
 impl Mix {
@@ -146,38 +195,42 @@ impl Mix {
             update: 1,
             remove: 1,
             upsert: 1,
+            get_or_insert: 0,
+            compute: 0,
         }
     }
 
-    pub fn read_99() -> Self {
+    /// Constructs a workload with the given read percentage, the rest inserts.
+    pub fn with_read(read: usize) -> Self {
         Self {
-            read: 99,
-            insert: 1,
+            read,
+            insert: 100 - read,
             update: 0,
             remove: 0,
             upsert: 0,
+            get_or_insert: 0,
+            compute: 0,
         }
     }
 
-    /// Constructs a read-only workload.
-    pub fn read_100() -> Self {
-        Self {
-            read: 100,
-            insert: 0,
-            update: 0,
-            remove: 0,
-            upsert: 0,
-        }
+    /// The percentage of each operation.
+    pub fn percents(&self) -> [(Operation, usize); 7] {
+        [
+            (Operation::Read, self.read),
+            (Operation::Insert, self.insert),
+            (Operation::Remove, self.remove),
+            (Operation::Update, self.update),
+            (Operation::Upsert, self.upsert),
+            (Operation::GetOrInsert, self.get_or_insert),
+            (Operation::Compute, self.compute),
+        ]
     }
 
-    // Assuming 'Operation' enum is defined similarly to the previous examples
     pub fn to_ops(&self) -> Vec<Operation> {
         let mut list = Vec::with_capacity(100);
-        list.extend(std::iter::repeat(Operation::Read).take(self.read as usize));
-        list.extend(std::iter::repeat(Operation::Insert).take(self.insert as usize));
-        list.extend(std::iter::repeat(Operation::Remove).take(self.remove as usize));
-        list.extend(std::iter::repeat(Operation::Update).take(self.update as usize));
-        list.extend(std::iter::repeat(Operation::Upsert).take(self.upsert as usize));
+        for (op, percent) in self.percents() {
+            list.extend(std::iter::repeat_n(op, percent));
+        }
         list.shuffle(&mut rand::thread_rng());
         list
     }
@@ -190,53 +243,259 @@ pub struct SharedMapTestConfig<'a> {
     pub prefill: usize,
     pub operations: &'a Vec<Operation>,
     pub keys_needed_per_thread: usize,
+    /// How reads, updates and removes pick their keys.
+    pub key_distribution: KeyDistribution,
+    pub pinning: Pinning,
+    /// Time one operation in this many for the latency histogram, 1 times them all.
+    pub sample_every: usize,
+    /// Records the progress of each run over time, if set.
+    pub timelines: Option<&'a TimelineRecorder>,
+    /// Count the heap use of the map after prefill and after the run, from
+    /// just before the map is created.
+    pub count_allocations: bool,
+    /// Operations per second of all threads together. When set each thread
+    /// issues its share on a fixed schedule, open loop, instead of starting
+    /// the next operation as soon as the last one finishes.
+    pub target_rate: Option<f64>,
+    /// Check the outcome of each operation and the contents of the map
+    /// after the run, panicking if the map lost or invented keys.
+    pub verify: bool,
+}
+
+/// How one thread times its operations.
+struct OpTimer<'a> {
+    sample_every: usize,
+    timer_overhead: u64,
+    histogram: Histogram,
+    /// Nanoseconds between operations in open loop mode. Operations are timed
+    /// from when they were due, so time spent queued behind a slow operation
+    /// counts towards the latency rather than being omitted.
+    interval: Option<f64>,
+    progress: &'a ProgressCounter,
+}
+
+/// What one thread did to the map in verify mode, to check the map against
+/// once the run is over.
+#[derive(Debug, Default)]
+struct OpLog {
+    prefill: usize,
+    /// Reads of prefilled keys must hit. Only true when nothing is removed.
+    check_reads: bool,
+    /// Inserts of new keys must succeed. Only true when no operation can
+    /// write a key before the thread it belongs to inserts it.
+    check_inserts: bool,
+    /// Indexes of the keys this thread was given to insert.
+    new_keys: Range<usize>,
+    inserts: usize,
+    /// Indexes of the keys this thread removed.
+    removed: Vec<usize>,
+    /// Indexes of the keys this thread upserted, got or inserted, or computed.
+    written: Vec<usize>,
+    /// Operations the map reported as failed. Write ops can't fail.
+    failed: usize,
+    failed_checks: usize,
+    errors: Vec<String>,
 }
+
+impl OpLog {
+    /// Errors kept per thread, the rest are only counted.
+    const MAX_ERRORS: usize = 10;
+
+    fn record(&mut self, op: Operation, index: usize, success: bool) {
+        if !success && !op.writes() {
+            self.failed += 1;
+        }
+        match op {
+            Operation::Read if self.check_reads && index < self.prefill && !success => {
+                self.error(format!("read of prefilled key {index} missed"))
+            }
+            Operation::Insert => {
+                // Once the new keys run out they are reused, so only the
+                // first insert of each is expected to succeed
+                if self.check_inserts && self.inserts < self.new_keys.len() && !success {
+                    self.error(format!("insert of new key {index} found it already there"));
+                }
+                self.inserts += 1;
+            }
+            Operation::Remove if success => self.removed.push(index),
+            op if op.writes() => self.written.push(index),
+            _ => {}
+        }
+    }
+
+    fn inserted(&self) -> Range<usize> {
+        self.new_keys.start..self.new_keys.start + self.inserts.min(self.new_keys.len())
+    }
+
+    fn error(&mut self, error: String) {
+        if self.errors.len() < Self::MAX_ERRORS {
+            self.errors.push(error);
+        }
+        self.failed_checks += 1;
+    }
+}
+
+/// Waits until `due`, sleeping while it is far off and spinning for the last
+/// stretch, as sleeps overshoot by tens of microseconds.
+fn wait_until(due: Instant) {
+    const SPIN: Duration = Duration::from_micros(100);
+    loop {
+        let now = Instant::now();
+        if now >= due {
+            return;
+        }
+        let remaining = due - now;
+        if remaining > SPIN {
+            thread::sleep(remaining - SPIN);
+        } else {
+            std::hint::spin_loop();
+        }
+    }
+}
+
 fn run_ops<H: CollectionHandle>(
     dict: &H, // Assuming you have a ConcurrentDictionary type
     keys: &Arc<Keys<H::Key>>,
     op_mix: &[Operation],
     ops_per_thread: usize,
-    keys_needed_per_thread: usize,
-) -> usize {
+    sampler: &mut KeySampler,
+    timer: &mut OpTimer,
+    mut log: Option<&mut OpLog>,
+) {
     let mut rng = thread_rng();
     let op_mix_count = op_mix.len();
-    let mut total_success = 0;
-    let mut new_keys = keys.alloc_n(keys_needed_per_thread).iter().cycle();
+    if let Some(log) = log.as_deref_mut() {
+        log.new_keys = sampler.new_keys();
+    }
+    let start = Instant::now();
 
     for i in 0..ops_per_thread {
         let op = op_mix[i % op_mix_count];
-        let r = rng.gen::<usize>(); // Generate a random usize
+        let index = match op {
+            Operation::Insert => sampler.next_new(),
+            _ => sampler.next(&mut rng),
+        };
+        let key = keys.get(index).clone();
+
+        // only the map call is timed, or in open loop everything since the
+        // op was due
+        let due = timer
+            .interval
+            .map(|interval| start + Duration::from_nanos((i as f64 * interval) as u64));
+        if let Some(due) = due {
+            wait_until(due);
+        }
+        let op_start = (i % timer.sample_every == 0).then(|| due.unwrap_or_else(Instant::now));
         let success = match op {
-            Operation::Read => dict.get(&keys.random(r)),
-            Operation::Insert => dict.insert(new_keys.next().unwrap().clone()),
-            Operation::Remove => dict.remove(&keys.random(r)),
-            Operation::Update => {
-                dict.update(&keys.random(r))
-                // if let Some(existing_value) = dict.get(&keys.random(r)) {
-                //     dict.insert(keys.random(r), existing_value + 1).is_some()
-                // } else {
-                //     false
-                // }
-            }
-            Operation::Upsert => {
-                // Note: Rust's `insert` always returns the old value, even if the key didn't exist before
-                //let old_value = dict.insert(keys.random(r), 1);
-                //old_value.is_none() || old_value.unwrap() == 0
-                dict.update(&keys.random(r))
-            }
+            Operation::Read => dict.get(&key),
+            Operation::Insert => dict.insert(key),
+            Operation::Remove => dict.remove(&key),
+            Operation::Update => dict.update(&key),
+            Operation::Upsert => dict.upsert(key),
+            Operation::GetOrInsert => dict.get_or_insert(key),
+            Operation::Compute => dict.compute(key),
         };
 
-        total_success += if success { 0 } else { 1 };
+        if let Some(op_start) = op_start {
+            let elapsed = op_start.elapsed().as_nanos() as u64;
+            timer.histogram.record(elapsed.saturating_sub(timer.timer_overhead));
+        }
+
+        if let Some(log) = log.as_deref_mut() {
+            log.record(op, index, success);
+        }
+
+        if i % PUBLISH_EVERY == 0 {
+            timer.progress.publish(i);
+        }
     }
 
-    total_success
+    timer.progress.publish(ops_per_thread);
+}
+
+/// Checks the map holds exactly the prefilled and inserted keys that were
+/// not removed, panicking with the first errors found if not.
+fn verify_map<H: Collection>(
+    name: &str,
+    collection: &H,
+    keys: &Keys<<H::Handle as CollectionHandle>::Key>,
+    prefill: usize,
+    logs: &[OpLog],
+) {
+    let mut errors: Vec<String> = logs.iter().flat_map(|log| log.errors.iter().cloned()).collect();
+    let mut error_count: usize = logs.iter().map(|log| log.failed_checks).sum();
+    let mut error = |e: String| {
+        if errors.len() < OpLog::MAX_ERRORS {
+            errors.push(e);
+        }
+        error_count += 1;
+    };
+
+    let mut expected = vec![false; keys.len()];
+    expected[..prefill].fill(true);
+    for log in logs {
+        expected[log.inserted()].fill(true);
+    }
+    let mut written = vec![false; keys.len()];
+    for &index in logs.iter().flat_map(|log| &log.written) {
+        written[index] = true;
+        expected[index] = true;
+    }
+    // A key that was both written and removed may or may not be there, as
+    // the logs don't say which came last
+    let mut unknown = vec![false; keys.len()];
+    for &index in logs.iter().flat_map(|log| &log.removed) {
+        if written[index] {
+            unknown[index] = true;
+        } else if !expected[index] {
+            error(format!("remove of key {index} succeeded but it was not there"));
+        }
+        expected[index] = false;
+    }
+
+    // Publish writes that are still pending, for maps like evmap that only
+    // show them to readers on refresh
+    collection.prefill_complete();
+
+    // Also look up the keys that were never used, to catch maps that find
+    // everything
+    let handle = collection.pin();
+    let mut found_unknown = 0;
+    for (index, &expected) in expected.iter().enumerate() {
+        let found = handle.get(keys.get(index));
+        if unknown[index] {
+            found_unknown += found as usize;
+            continue;
+        }
+        match (expected, found) {
+            (true, false) => error(format!("key {index} is missing")),
+            (false, true) => error(format!("key {index} is there but was never inserted or was removed")),
+            _ => {}
+        }
+    }
+
+    let expected_len = expected.iter().filter(|&&e| e).count() + found_unknown;
+    if let Some(len) = collection.len() {
+        if len != expected_len {
+            error(format!("map has {len} items, expected {expected_len}"));
+        }
+    }
+
+    let failed: usize = logs.iter().map(|log| log.failed).sum();
+    if error_count > 0 {
+        panic!(
+            "{name} failed verification with {error_count} errors, {failed} ops failed:\n  {}",
+            errors.join("\n  ")
+        );
+    }
+    println!("  verified {expected_len} items, {failed} ops failed as expected");
 }
 
 pub fn run_shared_map_test<'a, H: Collection>(
     name: &'a str,
-    collection: Arc<H>,
+    create: impl FnOnce() -> H,
     config: &SharedMapTestConfig,
-    keys: &'a Arc<Keys<<<H as Collection>::Handle as CollectionHandle>::Key>>,
+    keys: &Arc<Keys<<<H as Collection>::Handle as CollectionHandle>::Key>>,
 ) -> Measurement<'a> {
     let num_threads = config.thread_count;
 
@@ -245,7 +504,34 @@ pub fn run_shared_map_test<'a, H: Collection>(
     let barrier = Arc::new(Barrier::new(num_threads + 1));
     let mut thread_handles = Vec::with_capacity(num_threads);
     let ops_per_thread = config.total_ops / num_threads;
-    let results = Arc::new(Mutex::new(Vec::<Duration>::new()));
+    let results: ThreadResults = Arc::new(Mutex::new(Vec::with_capacity(num_threads)));
+    // the threads hand their histograms back rather than freeing them while
+    // allocations are counted
+    let mut histograms = (0..num_threads).map(|_| Histogram::new()).collect::<Vec<_>>().into_iter();
+    let latencies = Arc::new(Mutex::new(Vec::with_capacity(num_threads)));
+    let operations = Arc::new(config.operations.clone());
+    let sample_every = config.sample_every.max(1);
+    let interval = config.target_rate.map(|rate| num_threads as f64 * 1e9 / rate);
+    let timer_overhead = timer_overhead();
+    let progress: Arc<Vec<ProgressCounter>> = Arc::new((0..num_threads).map(|_| ProgressCounter::default()).collect());
+    let logs = Arc::new(Mutex::new(Vec::with_capacity(num_threads)));
+    let check_reads = !config.operations.contains(&Operation::Remove);
+    let check_inserts = !config.operations.iter().any(|op| op.writes());
+    let verify = config.verify;
+    let prefill = config.prefill;
+    let key_count = prefill + num_threads * config.keys_needed_per_thread;
+    let sampler = KeySampler::new(config.key_distribution, prefill, key_count);
+    let core_info = match config.pinning {
+        Pinning::None => None,
+        _ => Some(get_core_info().expect("Failed to get core IDs")),
+    };
+
+    // Everything the test needs is allocated by now, so counting from here
+    // sees only the map, including its initial table
+    if config.count_allocations {
+        allocator::start_counting();
+    }
+    let collection = Arc::new(create());
 
     keys.reset();
     let mut new_keys = keys.alloc_n(config.prefill).iter().cycle();
@@ -255,56 +541,119 @@ pub fn run_shared_map_test<'a, H: Collection>(
     }
 
     collection.prefill_complete();
+    let after_prefill = config.count_allocations.then(allocator::stats);
 
-    // uncomment for core affinity
-    // affinity: let core_ids = get_core_ids().expect("Failed to get core IDs");
-
-    for _ in 0..num_threads {
-        let operations = config.operations.clone();
+    for n in 0..num_threads {
+        let operations = operations.clone();
         let keys_needed_per_thread = config.keys_needed_per_thread;
         let barrier = barrier.clone();
         let results_clone = results.clone();
+        let latencies = latencies.clone();
         let collection = collection.clone();
         let keys = keys.clone();
-        // affinity: let core_id = core_ids[n % core_ids.len()];
-        // affinity: let core_id_usize = core_id.id as usize;
+        let progress = progress.clone();
+        let logs = logs.clone();
+        let sampler = sampler.clone();
+        let histogram = histograms.next().unwrap();
+        let cpus = core_info
+            .as_ref()
+            .and_then(|core_info| config.pinning.cpu_set(core_info, n));
 
         let handle = thread::spawn(move || {
-            // affinity: set_thread_affinity(&[core_id_usize]).expect("Failed to set thread affinity");
+            if let Some(cpus) = cpus {
+                set_thread_affinity(&cpus).expect("Failed to set thread affinity");
+            }
             let dict = collection.pin();
+            let mut timer = OpTimer {
+                sample_every,
+                timer_overhead,
+                histogram,
+                interval,
+                progress: &progress[n],
+            };
+            let mut log = verify.then(|| OpLog {
+                prefill,
+                check_reads,
+                check_inserts,
+                ..OpLog::default()
+            });
+            let mut sampler = sampler.for_thread(keys.alloc_range(keys_needed_per_thread));
+            let counters = PerfCounters::open();
             barrier.wait();
+            counters.start();
             let start_time = Instant::now();
             run_ops(
                 &dict,
                 &keys,
                 &operations,
                 ops_per_thread,
-                keys_needed_per_thread,
+                &mut sampler,
+                &mut timer,
+                log.as_mut(),
             );
 
             let elapsed = start_time.elapsed();
+            let counters = counters.stop();
             let mut results = results_clone.lock().unwrap();
-            results.push(elapsed);
+            results.push(ThreadResult { elapsed, ops: ops_per_thread as u64, counters });
+            latencies.lock().unwrap().push(timer.histogram);
+            logs.lock().unwrap().extend(log);
         });
 
         thread_handles.push(handle);
     }
 
     barrier.wait();
+    let start_time = Instant::now();
+    let sampler = config
+        .timelines
+        .map(|recorder| Sampler::start(progress.clone(), recorder.interval, start_time));
     for handle in thread_handles {
         handle.join().unwrap();
     }
+    let wall_time = start_time.elapsed();
+    let after_run = config.count_allocations.then(allocator::stats);
+    if config.count_allocations {
+        allocator::stop_counting();
+    }
 
-    let real_total_ops = ops_per_thread as u64 * num_threads as u64;
-    let average_duration = calc_av_nanos(results, real_total_ops);
+    if let (Some(recorder), Some(sampler)) = (config.timelines, sampler) {
+        recorder.push(Timeline {
+            name: name.to_string(),
+            thread_count: num_threads as u64,
+            samples: sampler.finish(),
+        });
+    }
 
-    println!("avg: {:8.2} ns", average_duration);
+    let mut measurement = Measurement::from_threads(name, &results, wall_time);
+    measurement.target_rate = config.target_rate;
+    let latencies = latencies
+        .lock()
+        .unwrap()
+        .drain(..)
+        .reduce(|mut all, histogram| {
+            all.merge(&histogram);
+            all
+        })
+        .unwrap();
+    measurement.percentiles = Some(latencies.percentiles());
+    if config.target_rate.is_some() {
+        // Time in the threads is mostly spent waiting for the schedule, so
+        // report the mean of the latencies measured from when ops were due
+        measurement.latency = latencies.mean();
+    }
+    measurement.footprint = after_prefill.zip(after_run).map(|(after_prefill, after_run)| Footprint {
+        prefill: config.prefill,
+        after_prefill,
+        after_run,
+    });
 
-    Measurement {
-        name,
-        latency: average_duration,
-        thread_count: num_threads as u64,
+    println!("{}", measurement.summary());
+
+    if config.verify {
+        verify_map(name, collection.as_ref(), keys, config.prefill, &logs.lock().unwrap());
     }
+    measurement
 }
 
 pub(crate) trait MapAdapter<K, V> {
@@ -346,8 +695,190 @@ where
     println!("avg: {:8.2} ns", average_duration);
 
     Measurement {
-        name,
-        latency: average_duration,
-        thread_count: (prefill / 1000) as u64,
+        items: Some(prefill as u64),
+        ..Measurement::new(name, average_duration, 1)
+    }
+}
+
+/// Measures the heap use of a non-shared map after inserting `prefill` items
+/// and after reading them all back. The latency is the average insert time.
+pub(crate) fn run_map_footprint<'a, M>(name: &'a str, prefill: usize, create: impl FnOnce() -> M) -> Measurement<'a>
+where
+    M: MapAdapter<u64, u64>,
+{
+    print!("map footprint {name} ({prefill} items) ... ");
+
+    allocator::start_counting();
+    let mut map = create();
+    let start_time = Instant::now();
+    for i in 0..prefill {
+        map.insert(i as u64, 1);
+    }
+    let elapsed = start_time.elapsed();
+    let after_prefill = allocator::stats();
+
+    for i in 0..prefill {
+        std::hint::black_box(map.get(&(i as u64)));
+    }
+    let after_run = allocator::stats();
+    drop(map);
+    allocator::stop_counting();
+
+    let footprint = Footprint {
+        prefill,
+        after_prefill,
+        after_run,
+    };
+    println!("{}", footprint.summary());
+
+    Measurement {
+        footprint: Some(footprint),
+        ..Measurement::new(name, elapsed.as_nanos() as f64 / prefill.max(1) as f64, 1)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::map_adapters::registry;
+    use crate::map_adapters::{BFixCollection, EvMapCollection, NopCollection, SccCollection, StdHashMapCollection};
+
+    fn verify<C: Collection>(operations: &Vec<Operation>)
+    where
+        C::Handle: CollectionHandle<Key = u64>,
+    {
+        let config = SharedMapTestConfig {
+            thread_count: 2,
+            total_ops: 10_000,
+            prefill: 1000,
+            operations,
+            keys_needed_per_thread: 10_000 * 5 / 100 / 2,
+            key_distribution: KeyDistribution::Uniform,
+            pinning: Pinning::None,
+            sample_every: 16,
+            timelines: None,
+            count_allocations: false,
+            target_rate: None,
+            verify: true,
+        };
+        let keys = Arc::new(Keys::new(1000 + 500 + 1000));
+        run_shared_map_test("test", || C::with_capacity(1500), &config, &keys);
+    }
+
+    fn mix(remove: usize) -> Vec<Operation> {
+        Mix { read: 85 - remove, insert: 5, remove, update: 5, upsert: 5, ..Mix::default() }.to_ops()
+    }
+
+    #[test]
+    fn test_verify_passes_std_map() {
+        verify::<StdHashMapCollection<u64, u64, ahash::RandomState>>(&mix(0));
+        verify::<StdHashMapCollection<u64, u64, ahash::RandomState>>(&mix(5));
+    }
+
+    /// A map's value for a key, to check what the write ops left there.
+    pub(crate) trait Values {
+        fn value(&self, key: u64) -> Option<u64>;
+    }
+
+    const WRITE_OPS: [Operation; 3] = [Operation::Upsert, Operation::GetOrInsert, Operation::Compute];
+
+    fn write<H: CollectionHandle<Key = u64>>(handle: &H, op: Operation, key: u64) -> bool {
+        match op {
+            Operation::Upsert => handle.upsert(key),
+            Operation::GetOrInsert => handle.get_or_insert(key),
+            Operation::Compute => handle.compute(key),
+            _ => unreachable!("{op:?} is not a write op"),
+        }
+    }
+
+    /// Runs each op on its own keys from several threads at once. Only one
+    /// call per key may find it missing, and compute must count every call.
+    fn contended_writes<C: Collection + Values>(ops: &[Operation])
+    where
+        C::Handle: CollectionHandle<Key = u64>,
+    {
+        const THREADS: usize = 4;
+        const KEYS: u64 = 1000;
+
+        let map = Arc::new(C::with_capacity(ops.len() * KEYS as usize));
+        let barrier = Arc::new(Barrier::new(THREADS));
+        let threads: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let (map, barrier, ops) = (map.clone(), barrier.clone(), ops.to_vec());
+                thread::spawn(move || {
+                    let handle = map.pin();
+                    barrier.wait();
+                    ops.iter()
+                        .enumerate()
+                        .map(|(i, &op)| {
+                            let keys = i as u64 * KEYS..(i as u64 + 1) * KEYS;
+                            keys.filter(|&key| !write(&handle, op, key)).count()
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let mut missing = vec![0; ops.len()];
+        for thread in threads {
+            for (total, count) in missing.iter_mut().zip(thread.join().unwrap()) {
+                *total += count;
+            }
+        }
+
+        for (i, &op) in ops.iter().enumerate() {
+            assert_eq!(missing[i], KEYS as usize, "{op:?} calls that found the key missing");
+            let expected = if op == Operation::Compute { THREADS as u64 } else { 0 };
+            for key in i as u64 * KEYS..(i as u64 + 1) * KEYS {
+                assert_eq!(map.value(key), Some(expected), "{op:?} of key {key}");
+            }
+        }
+    }
+
+    fn write_ops<C: Collection + Values>(ops: &[Operation])
+    where
+        C::Handle: CollectionHandle<Key = u64>,
+    {
+        let map = C::with_capacity(16);
+        let handle = map.pin();
+        for (key, &op) in (1..).zip(ops) {
+            assert!(!write(&handle, op, key), "{op:?} of a new key");
+            assert!(write(&handle, op, key), "{op:?} of a present key");
+        }
+        assert!(handle.insert(100));
+        assert!(!handle.insert(100));
+        assert!(handle.remove(&100));
+        assert!(!handle.remove(&100));
+        map.prefill_complete();
+        assert!((1..=ops.len() as u64).all(|key| handle.get(&key)));
+
+        contended_writes::<C>(ops);
+
+        let percent = |op| if ops.contains(&op) { 5 } else { 0 };
+        let (upsert, get_or_insert, compute) = (percent(Operation::Upsert), percent(Operation::GetOrInsert), percent(Operation::Compute));
+        let read = 90 - upsert - get_or_insert - compute;
+        verify::<C>(&Mix { read, insert: 5, remove: 5, upsert, get_or_insert, compute, ..Mix::default() }.to_ops());
+    }
+
+    // Each map runs the write ops the registry lets it
+    #[test]
+    fn test_write_ops() {
+        let maps = [
+            ("std-rwlock", write_ops::<StdHashMapCollection<u64, u64, ahash::RandomState>> as fn(&[Operation])),
+            ("scc", write_ops::<SccCollection<u64, u64, ahash::RandomState>>),
+            ("evmap", write_ops::<EvMapCollection<u64, u64, ahash::RandomState>>),
+            ("bfix", write_ops::<BFixCollection<u64, u64, ahash::RandomState>>),
+        ];
+        for (name, test) in maps {
+            let adapter = registry::find(name).unwrap();
+            let ops: Vec<Operation> = WRITE_OPS.into_iter().filter(|&op| adapter.supports(op)).collect();
+            test(&ops);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "failed verification")]
+    fn test_verify_fails_nop_map() {
+        verify::<NopCollection<u64, u64, ahash::RandomState>>(&mix(0));
     }
 }
@@ -1,73 +1,61 @@
 use rand::Rng;
 use std::collections::BTreeMap;
-use std::ffi::OsString;
 use std::mem::transmute;
-use std::os::raw::c_void;
-use std::os::windows::ffi::OsStringExt;
-use std::ptr::{self, null_mut};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Barrier, Mutex};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::Instant;
+#[cfg(target_os = "windows")]
 use winapi::shared::minwindef::DWORD;
+#[cfg(target_os = "windows")]
 use winapi::shared::winerror::ERROR_INSUFFICIENT_BUFFER;
+#[cfg(target_os = "windows")]
 use winapi::um::errhandlingapi::GetLastError;
-use winapi::um::memoryapi::{VirtualAlloc, VirtualAllocExNuma, VirtualFree};
-use winapi::um::processthreadsapi::{GetCurrentProcess, GetCurrentThread};
-use winapi::um::processtopologyapi::SetThreadGroupAffinity;
+#[cfg(target_os = "windows")]
 use winapi::um::sysinfoapi::{GetSystemInfo, SYSTEM_INFO};
-use winapi::um::winbase::{
-    FormatMessageW, FORMAT_MESSAGE_FROM_SYSTEM, FORMAT_MESSAGE_IGNORE_INSERTS,
-};
+#[cfg(target_os = "windows")]
 use winapi::um::winnt::{
-    RelationNumaNode, RelationProcessorCore, GROUP_AFFINITY, HANDLE, MEM_COMMIT, MEM_RESERVE,
-    PAGE_READWRITE, SYSTEM_LOGICAL_PROCESSOR_INFORMATION, SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX,
+    RelationNumaNode, RelationProcessorCore, LOGICAL_PROCESSOR_RELATIONSHIP,
+    SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX,
 };
-use winapi::um::heapapi::{HeapAlloc, HeapCreate, HeapDestroy, HeapFree};
 
-use crate::perf::{self, calc_av_nanos};
+use crate::affinity;
+use crate::numa_allocator::Heap;
+use crate::numa_mem::NumaBlock;
+use crate::perf::{self, ThreadResult, ThreadResults};
+use crate::perf_counters::PerfCounters;
+#[cfg(target_os = "windows")]
 use crate::perf_info::{get_last_error_message, GetLogicalProcessorInformationEx};
 
-#[derive(Copy, Clone)]
-struct CoreId {
-    processor_group: u16,
-    numa_mask: usize,
-    core_mask: usize,
-    numa_node_num: u32,
+/// A logical processor, described the way Windows processor groups see it:
+/// a group number and 64 bit masks within that group.
+#[derive(Copy, Clone, Debug)]
+pub struct CoreId {
+    pub processor_group: u16,
+    pub numa_mask: usize,
+    pub core_mask: usize,
+    // this processor and its hyper-threading siblings
+    pub smt_mask: usize,
+    pub numa_node_num: u32,
 }
 
-fn set_thread_affinity(core: &CoreId, numa_affinity: bool) -> Result<(), String> {
-    let current_thread: HANDLE = unsafe { GetCurrentThread() };
-    if current_thread.is_null() {
-        return Err("Failed to get current thread handle".to_string());
+impl CoreId {
+    /// Index of the logical processor across all groups.
+    pub fn cpu_index(&self) -> usize {
+        self.processor_group as usize * 64 + self.core_mask.trailing_zeros() as usize
     }
+}
 
-    let mask = if numa_affinity {
-        core.numa_mask
-    } else {
-        core.core_mask
-    };
-
-    let mut group_mask: GROUP_AFFINITY = unsafe { std::mem::zeroed() };
-    group_mask.Mask = mask;
-    group_mask.Group = core.processor_group;
-
-    let result = unsafe { SetThreadGroupAffinity(current_thread, &group_mask, ptr::null_mut()) };
-
-    if result == 0 {
-        return Err(format!(
-            "Failed to set thread affinity for core {:X}:{:X}: {}",
-            mask,
-            core.processor_group,
-            get_last_error_message(),
-        ));
+impl CoreId {
+    // how many of this processor's SMT siblings come before it
+    fn smt_rank(&self) -> u32 {
+        (self.smt_mask & (self.core_mask - 1)).count_ones()
     }
-
-    Ok(())
 }
 
-// distribute the ordering of CoreId evenly over the numa nodes
-fn distribute_numa_cores(core_ids: Vec<CoreId>) -> Vec<CoreId> {
+// distribute the ordering of CoreId evenly over the numa nodes, using every
+// physical core of a node before its SMT siblings
+pub(crate) fn distribute_numa_cores(core_ids: Vec<CoreId>) -> Vec<CoreId> {
     let mut cores_by_numa = BTreeMap::<u32, Vec<CoreId>>::new();
     let mut rearranged_core_ids = Vec::new();
 
@@ -78,6 +66,11 @@ fn distribute_numa_cores(core_ids: Vec<CoreId>) -> Vec<CoreId> {
             .push(core_id.clone());
     }
 
+    // cores are popped from the back, so the first siblings go last
+    for v in cores_by_numa.values_mut() {
+        v.sort_by_key(|c| std::cmp::Reverse(c.smt_rank()));
+    }
+
     while rearranged_core_ids.len() < core_ids.len() {
         for (_n, v) in cores_by_numa.iter_mut() {
             if let Some(c) = v.pop() {
@@ -89,6 +82,7 @@ fn distribute_numa_cores(core_ids: Vec<CoreId>) -> Vec<CoreId> {
     rearranged_core_ids
 }
 
+#[cfg(target_os = "windows")]
 pub fn get_num_cpus() -> usize {
     // Get system information
     let mut system_info: SYSTEM_INFO = unsafe { std::mem::zeroed() };
@@ -96,19 +90,50 @@ pub fn get_num_cpus() -> usize {
     return system_info.dwNumberOfProcessors as usize;
 }
 
+#[cfg(target_os = "linux")]
+pub fn get_num_cpus() -> usize {
+    crate::sysfs::SysFs::default()
+        .online_cpus()
+        .map(|cpus| cpus.len())
+        .unwrap_or_else(|_| thread::available_parallelism().map_or(1, |n| n.get()))
+}
+
 pub struct CoreInfo {
     pub ids: Vec<CoreId>,
-    pub num_numa_nodes: usize,
+    // ids of the numa nodes that have cpus, in order. Node ids can have gaps.
+    pub numa_nodes: Vec<u32>,
 }
 
+impl CoreInfo {
+    /// Position of a node in `numa_nodes`.
+    pub fn numa_node_index(&self, numa_node: u32) -> usize {
+        self.numa_nodes
+            .iter()
+            .position(|&n| n == numa_node)
+            .unwrap_or_else(|| panic!("Unknown numa node {numa_node}"))
+    }
+
+    /// The node after this one, wrapping around. The same node if there is
+    /// only one.
+    pub fn next_numa_node(&self, numa_node: u32) -> u32 {
+        let index = self.numa_node_index(numa_node);
+        self.numa_nodes[(index + 1) % self.numa_nodes.len()]
+    }
+}
+
+#[cfg(target_os = "linux")]
 pub fn get_core_info() -> Result<CoreInfo, String> {
+    crate::sysfs::SysFs::default().core_info()
+}
+
+// Returns the raw GetLogicalProcessorInformationEx buffer for one relationship type
+#[cfg(target_os = "windows")]
+pub(crate) fn get_logical_processor_info(
+    relationship: LOGICAL_PROCESSOR_RELATIONSHIP,
+) -> Result<Vec<u8>, String> {
     // Determine buffer size needed for GetLogicalProcessorInformationEx
-    let mut core_infos = Vec::new();
-    let mut numa_node_set = std::collections::HashSet::new();
-    let relationship = RelationNumaNode;
-    let mut buffer = vec![0u8; 1];
-    let mut p_buffer_alloc = buffer.as_ptr();
-    let p_buffer = p_buffer_alloc as *mut SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX;
+    let buffer = vec![0u8; 1];
+    let p_buffer = buffer.as_ptr() as *mut SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX;
     let mut cb_buffer: DWORD = 1;
 
     let result =
@@ -132,8 +157,7 @@ pub fn get_core_info() -> Result<CoreInfo, String> {
 
     // Allocate buffer
     let mut buffer = vec![0u8; cb_buffer as usize];
-    let mut p_buffer_alloc = buffer.as_ptr();
-    let mut p_buffer = p_buffer_alloc as *mut SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX;
+    let p_buffer = buffer.as_mut_ptr() as *mut SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX;
 
     let result =
         unsafe { GetLogicalProcessorInformationEx(relationship, p_buffer, &mut cb_buffer) };
@@ -145,14 +169,46 @@ pub fn get_core_info() -> Result<CoreInfo, String> {
         ));
     }
 
-    let mut p_cur = p_buffer as *const u8;
-    let p_end = unsafe { p_cur.add(cb_buffer as usize) };
+    buffer.truncate(cb_buffer as usize);
+    Ok(buffer)
+}
+
+// Calls f for each SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX record in the buffer
+#[cfg(target_os = "windows")]
+pub(crate) fn for_each_processor_info(
+    buffer: &[u8],
+    mut f: impl FnMut(&SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX),
+) {
+    let mut p_cur = buffer.as_ptr();
+    let p_end = unsafe { p_cur.add(buffer.len()) };
 
-    let mut idx = 0;
     while p_cur < p_end {
-        let p_buffer_ref = unsafe { &*p_buffer };
+        let p_buffer_ref = unsafe { &*(p_cur as *const SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX) };
+        f(p_buffer_ref);
+        p_cur = unsafe { p_cur.add(p_buffer_ref.Size as usize) };
+    }
+}
 
-        if p_buffer_ref.Relationship == relationship {
+#[cfg(target_os = "windows")]
+pub fn get_core_info() -> Result<CoreInfo, String> {
+    let mut core_infos = Vec::new();
+    let mut numa_node_set = std::collections::BTreeSet::new();
+
+    // (group, mask) of each physical core, used to find SMT siblings
+    let mut smt_masks = Vec::new();
+    let buffer = get_logical_processor_info(RelationProcessorCore)?;
+    for_each_processor_info(&buffer, |p_buffer_ref| {
+        if p_buffer_ref.Relationship == RelationProcessorCore {
+            // a physical core never spans processor groups
+            let info = unsafe { p_buffer_ref.u.Processor() };
+            let g = &info.GroupMask[0];
+            smt_masks.push((g.Group, g.Mask));
+        }
+    });
+
+    let buffer = get_logical_processor_info(RelationNumaNode)?;
+    for_each_processor_info(&buffer, |p_buffer_ref| {
+        if p_buffer_ref.Relationship == RelationNumaNode {
             let info = unsafe { &p_buffer_ref.u.NumaNode() };
 
             let processor_mask = info.GroupMask.Mask;
@@ -161,10 +217,17 @@ pub fn get_core_info() -> Result<CoreInfo, String> {
 
             for i in 0..64 {
                 if (processor_mask & (1 << i)) != 0 {
+                    let core_mask = 1 << i;
+                    let smt_mask = smt_masks
+                        .iter()
+                        .find(|(group, mask)| *group == processor_group && mask & core_mask != 0)
+                        .map_or(core_mask, |(_, mask)| *mask);
+
                     core_infos.push(CoreId {
                         processor_group,
-                        core_mask: 1 << i,
+                        core_mask,
                         numa_mask: processor_mask,
+                        smt_mask,
                         numa_node_num,
                     });
                 }
@@ -172,14 +235,11 @@ pub fn get_core_info() -> Result<CoreInfo, String> {
 
             numa_node_set.insert(numa_node_num);
         }
-        p_cur = unsafe { p_cur.add(p_buffer_ref.Size as usize) };
-        p_buffer = p_cur as *mut SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX;
-        idx += 1;
-    }
+    });
 
     Ok(CoreInfo {
         ids: distribute_numa_cores(core_infos),
-        num_numa_nodes: numa_node_set.len(),
+        numa_nodes: numa_node_set.into_iter().collect(),
     })
 }
 
@@ -202,7 +262,7 @@ pub(crate) fn run_independent_memory_access_test(
     print!("Mem {name:8} (threads {thread_count:>3}) ... ");
 
     let core_info = get_core_info().expect("Failed to get core IDs");
-    let results = Arc::new(Mutex::new(Vec::<Duration>::new()));
+    let results: ThreadResults = Arc::new(Mutex::new(Vec::new()));
     let barrier = Arc::new(Barrier::new(thread_count + 1));
 
     let mut handles = vec![];
@@ -211,64 +271,40 @@ pub(crate) fn run_independent_memory_access_test(
         let results_clone = Arc::clone(&results);
         let barrier = barrier.clone();
         let thread_affinity = affinity.clone();
-        let num_numa_nodes = core_info.num_numa_nodes as u32;
+        let wrong_numa_node = core_info.next_numa_node(core_id.numa_node_num);
 
         let handle = thread::spawn(move || {
-            let memory_block_ptr = match thread_affinity {
-                AffinityType::NoAffinity => unsafe {
-                    VirtualAlloc(
-                        null_mut(),
-                        block_size,
-                        MEM_COMMIT | MEM_RESERVE,
-                        PAGE_READWRITE,
-                    )
-                },
+            let mut memory_block = match thread_affinity {
+                AffinityType::NoAffinity => NumaBlock::alloc(block_size),
                 AffinityType::NumaNodeAffinity => {
-                    set_thread_affinity(&core_id, true).expect("Failed to set thread affinity");
-                    unsafe {
-                        VirtualAllocExNuma(
-                            GetCurrentProcess(),
-                            ptr::null_mut(),
-                            block_size,
-                            winapi::um::winnt::MEM_COMMIT | winapi::um::winnt::MEM_RESERVE,
-                            winapi::um::winnt::PAGE_READWRITE,
-                            core_id.numa_node_num,
-                        )
-                    }
+                    affinity::pin_to_numa_node(&core_id).expect("Failed to set thread affinity");
+                    NumaBlock::alloc_on_node(block_size, core_id.numa_node_num)
                 }
                 AffinityType::NumaMismatch => {
-                    set_thread_affinity(&core_id, true).expect("Failed to set thread affinity");
-                    unsafe {
-                        let wrong_numa_node = (core_id.numa_node_num + 1) % num_numa_nodes;
-
-                        VirtualAllocExNuma(
-                            GetCurrentProcess(),
-                            ptr::null_mut(),
-                            block_size,
-                            winapi::um::winnt::MEM_COMMIT | winapi::um::winnt::MEM_RESERVE,
-                            winapi::um::winnt::PAGE_READWRITE,
-                            wrong_numa_node,
-                        )
-                    }
+                    affinity::pin_to_numa_node(&core_id).expect("Failed to set thread affinity");
+                    NumaBlock::alloc_on_node(block_size, wrong_numa_node)
                 }
-            };
-
-            if memory_block_ptr.is_null() {
-                panic!("Failed to allocate memory");
             }
+            .expect("Failed to allocate memory");
+
+            // commit the pages up front, then check they landed where we asked
+            memory_block.touch();
+            match thread_affinity {
+                AffinityType::NoAffinity => Ok(()),
+                AffinityType::NumaNodeAffinity => memory_block.verify_node(core_id.numa_node_num),
+                AffinityType::NumaMismatch => memory_block.verify_node(wrong_numa_node),
+            }
+            .expect("Memory is not on the requested numa node");
 
-            // place an array on that memory
-            let mut u64_array = unsafe {
-                std::slice::from_raw_parts_mut(
-                    memory_block_ptr as *mut u64,
-                    block_size / std::mem::size_of::<u64>(),
-                )
-            };
+            // place an array on that memory, any bit pattern is a valid u64
+            let u64_array = unsafe { memory_block.as_mut_slice::<u64>() };
 
             // Fill the memory block with random u64 values
             let mut rng = rand::thread_rng();
 
+            let counters = PerfCounters::open();
             barrier.wait();
+            counters.start();
 
             let start_time = Instant::now();
             let mut reads_performed = 0;
@@ -291,37 +327,31 @@ pub(crate) fn run_independent_memory_access_test(
             }
 
             let elapsed = start_time.elapsed();
+            let counters = counters.stop();
 
             // Free the allocated memory for this thread
-            unsafe {
-                VirtualFree(memory_block_ptr, 0, winapi::um::winnt::MEM_RELEASE);
-            }
+            drop(memory_block);
 
+            let ops = TEST_LOOPS * if read_only { 1 } else { 2 };
             let mut results = results_clone.lock().unwrap();
-            results.push(elapsed);
+            results.push(ThreadResult { elapsed, ops, counters });
         });
 
         handles.push(handle);
     }
 
     barrier.wait();
+    let start_time = Instant::now();
 
     // Wait for all threads to finish
     for handle in handles {
         handle.join().unwrap();
     }
+    let wall_time = start_time.elapsed();
 
-    // Calculate averages
-    let op_count = thread_count as u64 * TEST_LOOPS * if read_only { 1 } else { 2 };
-    let average_duration = calc_av_nanos(results, op_count);
-
-    println!("avg: {:8.2} ns", average_duration);
-
-    perf::Measurement {
-        name,
-        latency: average_duration,
-        thread_count: thread_count as u64,
-    }
+    let measurement = perf::Measurement::from_threads(name, &results, wall_time);
+    println!("{}", measurement.summary());
+    measurement
 }
 
 pub(crate) fn run_fetch_add_test(
@@ -338,7 +368,7 @@ pub(crate) fn run_fetch_add_test(
         .collect();
 
     let barrier = Arc::new(Barrier::new(thread_count + 1));
-    let results = Arc::new(Mutex::new(Vec::<Duration>::new()));
+    let results: ThreadResults = Arc::new(Mutex::new(Vec::new()));
 
     let mut handles = vec![];
     for i in 0..thread_count {
@@ -347,16 +377,21 @@ pub(crate) fn run_fetch_add_test(
         let results_clone = Arc::clone(&results);
 
         let handle = thread::spawn(move || {
+            let counters = PerfCounters::open();
             barrier.wait();
+            counters.start();
 
             let start_time = Instant::now();
+            let mut ops = 0;
 
             loop {
                 let current_value = atomic_counter_clone.fetch_add(1, Ordering::SeqCst);
+                ops += 1;
                 if current_value >= INCREMENT_COUNT - 1 {
-                    let duration = start_time.elapsed();
+                    let elapsed = start_time.elapsed();
+                    let counters = counters.stop();
                     let mut results = results_clone.lock().unwrap();
-                    results.push(duration);
+                    results.push(ThreadResult { elapsed, ops, counters });
                     break;
                 }
             }
@@ -366,27 +401,17 @@ pub(crate) fn run_fetch_add_test(
     }
 
     barrier.wait();
+    let start_time = Instant::now();
 
     // Wait for all threads to finish
     for handle in handles {
         handle.join().unwrap();
     }
+    let wall_time = start_time.elapsed();
 
-    // Calculate statistics from the results
-    let op_count: u64 = atomic_counters
-        .iter()
-        .map(|counter| unsafe { (**counter).load(Ordering::SeqCst) })
-        .sum();
-
-    let average_duration = calc_av_nanos(results, op_count);
-
-    println!("avg: {:8.2} ns", average_duration);
-
-    perf::Measurement {
-        name,
-        latency: average_duration,
-        thread_count: thread_count as u64,
-    }
+    let measurement = perf::Measurement::from_threads(name, &results, wall_time);
+    println!("{}", measurement.summary());
+    measurement
 }
 
 pub(crate) fn run_mutex_test(
@@ -404,7 +429,7 @@ pub(crate) fn run_mutex_test(
         .collect();
 
     let barrier = Arc::new(Barrier::new(thread_count + 1));
-    let results = Arc::new(Mutex::new(Vec::<Duration>::new()));
+    let results: ThreadResults = Arc::new(Mutex::new(Vec::new()));
 
     let mut handles = vec![];
     for i in 0..thread_count {
@@ -413,20 +438,25 @@ pub(crate) fn run_mutex_test(
         let results_clone = Arc::clone(&results);
 
         let handle = thread::spawn(move || {
+            let counters = PerfCounters::open();
             barrier.wait();
+            counters.start();
 
             let start_time = Instant::now();
+            let mut ops = 0;
 
             loop {
                 // Acquire the mutex lock to modify the counter
                 let mut counter = mutex_counter_clone.lock().unwrap();
                 *counter += 1; // Increment the counter
                 let current_value = *counter;
+                ops += 1;
 
                 if current_value >= INCREMENT_COUNT {
-                    let duration = start_time.elapsed();
+                    let elapsed = start_time.elapsed();
+                    let counters = counters.stop();
                     let mut results = results_clone.lock().unwrap();
-                    results.push(duration);
+                    results.push(ThreadResult { elapsed, ops, counters });
                     break;
                 }
                 // The lock is automatically released when `counter` goes out of scope
@@ -437,52 +467,40 @@ pub(crate) fn run_mutex_test(
     }
 
     barrier.wait();
+    let start_time = Instant::now();
 
     // Wait for all threads to finish
     for handle in handles {
         handle.join().unwrap();
     }
+    let wall_time = start_time.elapsed();
 
-    // Calculate statistics from the results
-    let counter_total: u64 = mutex_counters
-        .iter()
-        .map(|counter| *counter.lock().unwrap())
-        .sum();
-    let average_duration = calc_av_nanos(results, counter_total);
-
-    println!("avg: {:8.2} ns", average_duration);
-
-    perf::Measurement {
-        name,
-        latency: average_duration,
-        thread_count: thread_count as u64,
-    }
+    let measurement = perf::Measurement::from_threads(name, &results, wall_time);
+    println!("{}", measurement.summary());
+    measurement
 }
 
-fn allocate_atomic_u64_on_numa_nodes(num_numa_nodes: usize) -> Vec<*mut AtomicU64> {
-    (0..num_numa_nodes)
-        .map(|numa_node| {
-            let ptr = unsafe {
-                VirtualAllocExNuma(
-                    GetCurrentProcess(),
-                    ptr::null_mut(),
-                    std::mem::size_of::<AtomicU64>(),
-                    MEM_COMMIT | MEM_RESERVE,
-                    PAGE_READWRITE,
-                    numa_node as u32,
-                )
-            };
-
-            if ptr.is_null() {
-                panic!("Failed to allocate memory on NUMA node {}", numa_node);
-            }
+fn allocate_atomic_u64_on_numa_nodes(numa_nodes: &[u32]) -> Vec<NumaBlock> {
+    numa_nodes
+        .iter()
+        .map(|&numa_node| {
+            let mut block =
+                NumaBlock::alloc_on_node(std::mem::size_of::<AtomicU64>(), numa_node)
+                    .unwrap_or_else(|e| {
+                        panic!("Failed to allocate memory on NUMA node {}: {}", numa_node, e)
+                    });
+
+            block.touch();
+            block
+                .verify_node(numa_node)
+                .expect("Counter is not on the requested numa node");
 
-            let atomic_u64_ptr = ptr as *mut AtomicU64;
+            let atomic_u64_ptr = block.as_ptr() as *mut AtomicU64;
             unsafe {
                 (*atomic_u64_ptr).store(0, Ordering::Relaxed);
             } // Initialize to 0
 
-            atomic_u64_ptr
+            block
         })
         .collect()
 }
@@ -499,34 +517,48 @@ pub(crate) fn run_numa_fetch_add_test(
     let core_info = get_core_info().expect("Failed to get core IDs");
 
     // Allocate an atomic counter per NUMA node
-    let atomic_counters = allocate_atomic_u64_on_numa_nodes(core_info.num_numa_nodes);
+    let counter_blocks = allocate_atomic_u64_on_numa_nodes(&core_info.numa_nodes);
+    let atomic_counters: Vec<*mut AtomicU64> = counter_blocks
+        .iter()
+        .map(|block| block.as_ptr() as *mut AtomicU64)
+        .collect();
 
     let barrier = Arc::new(Barrier::new(thread_count + 1));
-    let results = Arc::new(Mutex::new(Vec::<Duration>::new()));
+    let results: ThreadResults = Arc::new(Mutex::new(Vec::new()));
 
     let mut handles = vec![];
     for thread_index in 0..thread_count {
         let core_id = core_info.ids[thread_index % core_info.ids.len()];
-        let numa_node = core_id.numa_node_num as usize;
+        let numa_node = core_info.numa_node_index(core_id.numa_node_num);
         let counter_as_usize =
             unsafe { transmute::<*mut AtomicU64, usize>(atomic_counters[numa_node]) };
         let barrier = barrier.clone();
         let results_clone = Arc::clone(&results);
 
         let handle = thread::spawn(move || {
-            set_thread_affinity(&core_id, numa_affinity).expect("Failed to set thread affinity");
+            if numa_affinity {
+                affinity::pin_to_numa_node(&core_id)
+            } else {
+                affinity::pin_to_core(&core_id)
+            }
+            .expect("Failed to set thread affinity");
             let counter_ptr = unsafe { transmute::<usize, *mut AtomicU64>(counter_as_usize) };
 
+            let counters = PerfCounters::open();
             barrier.wait();
+            counters.start();
 
             let start_time = Instant::now();
+            let mut ops = 0;
 
             loop {
                 let current_value = (unsafe { &*counter_ptr }).fetch_add(1, Ordering::SeqCst);
+                ops += 1;
                 if current_value >= INCREMENT_COUNT - 1 {
-                    let duration = start_time.elapsed();
+                    let elapsed = start_time.elapsed();
+                    let counters = counters.stop();
                     let mut results = results_clone.lock().unwrap();
-                    results.push(duration);
+                    results.push(ThreadResult { elapsed, ops, counters });
                     break;
                 }
             }
@@ -536,26 +568,20 @@ pub(crate) fn run_numa_fetch_add_test(
     }
 
     barrier.wait();
+    let start_time = Instant::now();
 
     // Wait for all threads to finish
     for handle in handles {
         handle.join().unwrap();
     }
+    let wall_time = start_time.elapsed();
 
-    let counter_total: u64 = atomic_counters
-        .iter()
-        .map(|counter| unsafe { (**counter).load(Ordering::SeqCst) })
-        .sum();
-
-    let average_duration = calc_av_nanos(results, counter_total);
+    // the counters live in counter_blocks, which must outlive the threads
+    drop(counter_blocks);
 
-    println!("avg: {:8.2} ns", average_duration);
-
-    perf::Measurement {
-        name,
-        latency: average_duration,
-        thread_count: thread_count as u64,
-    }
+    let measurement = perf::Measurement::from_threads(name, &results, wall_time);
+    println!("{}", measurement.summary());
+    measurement
 }
 
 
@@ -569,41 +595,38 @@ pub(crate) fn run_heapalloc_test(
 
     print!("HeapAlloc {name:8} (threads {thread_count:>3}, heaps {num_heaps:>2}) ... ");
 
-    let results = Arc::new(Mutex::new(Vec::<Duration>::new()));
+    let results: ThreadResults = Arc::new(Mutex::new(Vec::new()));
     let barrier = Arc::new(Barrier::new(thread_count + 1));
 
     // Create the specified number of heaps
-    let heaps: Vec<_> = (0..num_heaps)
-        .map(|_| unsafe { HeapCreate(0, 0, 0) })
-        .collect();
+    let heaps: Vec<_> = (0..num_heaps).map(|_| Arc::new(Heap::new())).collect();
 
     let mut handles = vec![];
     for thread_id in 0..thread_count {
         let results_clone = Arc::clone(&results);
         let barrier = barrier.clone();
         let heap_index = thread_id % num_heaps;
-        let heap_handle = heaps[heap_index];
-        let heap_id: usize = heap_handle as usize; // Cast to usize
+        // Assign a specific heap to this thread
+        let heap = heaps[heap_index].clone();
 
         let handle = thread::spawn(move || {
-            // Assign a specific heap to this thread
-
-            let heap = heap_id as *mut c_void;
-            let start_time = Instant::now();
             let mut writes_performed = 0;
             let mut rng = rand::thread_rng();
 
             // Circular buffer for allocated blocks
-            let mut allocated_blocks: [*mut c_void; DELAY_FREE] = [std::ptr::null_mut(); DELAY_FREE];
+            let mut allocated_blocks: [(*mut u8, usize); DELAY_FREE] = [(std::ptr::null_mut(), 0); DELAY_FREE];
             let mut head: usize = 0;
             let mut tail: usize = 0;
 
+            let counters = PerfCounters::open();
             barrier.wait();
+            counters.start();
+            let start_time = Instant::now();
 
             for _ in 0..TEST_LOOPS {
                 // Allocate memory from the assigned heap
                 let block_size = rng.gen_range(16..=1024);
-                let memory_block_ptr = unsafe { HeapAlloc(heap, 0, block_size) };
+                let memory_block_ptr = heap.alloc(block_size);
 
                 if memory_block_ptr.is_null() {
                     panic!("Failed to allocate memory");
@@ -620,53 +643,46 @@ pub(crate) fn run_heapalloc_test(
                 writes_performed += 1;
 
                 // Add the allocated block to the circular buffer
-                allocated_blocks[tail] = memory_block_ptr;
+                allocated_blocks[tail] = (memory_block_ptr, block_size);
                 tail = (tail + 1) % DELAY_FREE;
 
                 // If the buffer is full, free the oldest block
                 if head == tail {
-                    let block_to_free = allocated_blocks[head];
-                    unsafe { HeapFree(heap, 0, block_to_free) };
+                    let (block_to_free, size) = allocated_blocks[head];
+                    unsafe { heap.free(block_to_free, size) };
                     head = (head + 1) % DELAY_FREE;
                 }
             }
 
             while head != tail {
-                let block_to_free = allocated_blocks[head];
-                unsafe { HeapFree(heap, 0, block_to_free) };
+                let (block_to_free, size) = allocated_blocks[head];
+                unsafe { heap.free(block_to_free, size) };
                 head = (head + 1) % DELAY_FREE;
             }
 
             let elapsed = start_time.elapsed();
+            let counters = counters.stop();
 
             let mut results = results_clone.lock().unwrap();
-            results.push(elapsed);
+            results.push(ThreadResult { elapsed, ops: TEST_LOOPS, counters });
         });
 
         handles.push(handle);
     }
 
     barrier.wait();
+    let start_time = Instant::now();
 
     // Wait for all threads to finish
     for handle in handles {
         handle.join().unwrap();
     }
+    let wall_time = start_time.elapsed();
 
     // Destroy the heaps
-    for heap in heaps {
-        unsafe { HeapDestroy(heap) };
-    }
-
-    // Calculate averages
-    let op_count = thread_count as u64 * TEST_LOOPS;
-    let average_duration = calc_av_nanos(results, op_count);
+    drop(heaps);
 
-    println!("avg: {:8.2} ns", average_duration);
-
-    perf::Measurement {
-        name,
-        latency: average_duration,
-        thread_count: thread_count as u64,
-    }
+    let measurement = perf::Measurement::from_threads(name, &results, wall_time);
+    println!("{}", measurement.summary());
+    measurement
 }
\ No newline at end of file
@@ -0,0 +1,1032 @@
+use plotters::coord::ranged1d::{DefaultFormatting, ValueFormatter};
+use plotters::coord::types::RangedCoordf64;
+use plotters::element::DashedPathElement;
+use plotters::prelude::*;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use crate::allocator;
+use crate::palette::{self, Dash, Marker, Style};
+use crate::perf::Measurement;
+use crate::stats::Summary;
+use crate::timeline::Timeline;
+
+/// A value of a `Measurement` that can be charted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Metric {
+    /// Average latency per operation
+    Latency,
+    P50,
+    P90,
+    P99,
+    P999,
+    Max,
+    /// Time from starting the threads until the last one finished
+    WallTime,
+    /// Operations per second, all threads together
+    Throughput,
+    /// Operations per second of the slowest thread
+    ThreadThroughputMin,
+    ThreadThroughputMean,
+    /// Operations per second of the fastest thread
+    ThreadThroughputMax,
+    /// Hardware events per operation
+    Cycles,
+    Instructions,
+    L1dMisses,
+    LlcMisses,
+    DtlbMisses,
+    RemoteAccesses,
+    /// Heap bytes per item after prefill
+    BytesPerEntry,
+    /// Most heap bytes in use at once, over prefill and run
+    PeakBytes,
+    /// Heap allocations over prefill and run
+    Allocations,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 20] = [
+        Metric::Latency,
+        Metric::P50,
+        Metric::P90,
+        Metric::P99,
+        Metric::P999,
+        Metric::Max,
+        Metric::WallTime,
+        Metric::Throughput,
+        Metric::ThreadThroughputMin,
+        Metric::ThreadThroughputMean,
+        Metric::ThreadThroughputMax,
+        Metric::Cycles,
+        Metric::Instructions,
+        Metric::L1dMisses,
+        Metric::LlcMisses,
+        Metric::DtlbMisses,
+        Metric::RemoteAccesses,
+        Metric::BytesPerEntry,
+        Metric::PeakBytes,
+        Metric::Allocations,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::Latency => "latency",
+            Metric::P50 => "p50",
+            Metric::P90 => "p90",
+            Metric::P99 => "p99",
+            Metric::P999 => "p999",
+            Metric::Max => "max",
+            Metric::WallTime => "wall-time",
+            Metric::Throughput => "throughput",
+            Metric::ThreadThroughputMin => "thread-throughput-min",
+            Metric::ThreadThroughputMean => "thread-throughput-mean",
+            Metric::ThreadThroughputMax => "thread-throughput-max",
+            Metric::Cycles => "cycles",
+            Metric::Instructions => "instructions",
+            Metric::L1dMisses => "l1d-misses",
+            Metric::LlcMisses => "llc-misses",
+            Metric::DtlbMisses => "dtlb-misses",
+            Metric::RemoteAccesses => "remote-accesses",
+            Metric::BytesPerEntry => "bytes-per-entry",
+            Metric::PeakBytes => "peak-bytes",
+            Metric::Allocations => "allocations",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Metric::Latency => "Latency",
+            Metric::P50 => "p50 latency",
+            Metric::P90 => "p90 latency",
+            Metric::P99 => "p99 latency",
+            Metric::P999 => "p99.9 latency",
+            Metric::Max => "Max latency",
+            Metric::WallTime => "Wall time",
+            Metric::Throughput => "Throughput",
+            Metric::ThreadThroughputMin => "Slowest thread",
+            Metric::ThreadThroughputMean => "Average thread",
+            Metric::ThreadThroughputMax => "Fastest thread",
+            Metric::Cycles => "Cycles per op",
+            Metric::Instructions => "Instructions per op",
+            Metric::L1dMisses => "L1D misses per op",
+            Metric::LlcMisses => "LLC misses per op",
+            Metric::DtlbMisses => "dTLB misses per op",
+            Metric::RemoteAccesses => "Remote accesses per op",
+            Metric::BytesPerEntry => "Bytes per entry",
+            Metric::PeakBytes => "Peak heap",
+            Metric::Allocations => "Allocations",
+        }
+    }
+
+    pub fn value(&self, m: &Measurement) -> Option<f64> {
+        let p = m.percentiles.as_ref();
+        let t = m.throughput.as_ref();
+        let c = m.counters.as_ref();
+        let f = m.footprint.as_ref();
+        match self {
+            Metric::Latency => Some(m.latency),
+            Metric::P50 => p.map(|p| p.p50),
+            Metric::P90 => p.map(|p| p.p90),
+            Metric::P99 => p.map(|p| p.p99),
+            Metric::P999 => p.map(|p| p.p999),
+            Metric::Max => p.map(|p| p.max),
+            Metric::WallTime => t.map(|t| t.wall_secs),
+            Metric::Throughput => t.map(|t| t.ops_per_sec),
+            Metric::ThreadThroughputMin => t.map(|t| t.thread_ops_per_sec_min),
+            Metric::ThreadThroughputMean => t.map(|t| t.thread_ops_per_sec_mean),
+            Metric::ThreadThroughputMax => t.map(|t| t.thread_ops_per_sec_max),
+            Metric::Cycles => c.and_then(|c| c.cycles),
+            Metric::Instructions => c.and_then(|c| c.instructions),
+            Metric::L1dMisses => c.and_then(|c| c.l1d_misses),
+            Metric::LlcMisses => c.and_then(|c| c.llc_misses),
+            Metric::DtlbMisses => c.and_then(|c| c.dtlb_misses),
+            Metric::RemoteAccesses => c.and_then(|c| c.remote_accesses),
+            Metric::BytesPerEntry => f.map(|f| f.bytes_per_entry()),
+            Metric::PeakBytes => f.map(|f| f.after_run.peak_bytes as f64),
+            Metric::Allocations => f.map(|f| f.after_run.allocations as f64),
+        }
+    }
+
+    /// The 95% confidence interval of the value, for points measured over
+    /// several trials.
+    pub fn band(&self, m: &Measurement) -> Option<(f64, f64)> {
+        let summary = self.summary(m)?;
+        Some((summary.ci_low, summary.ci_high))
+    }
+
+    /// How the value varied over the trials and how many trials were kept.
+    /// Only latency and throughput are tracked per trial.
+    pub fn summary(&self, m: &Measurement) -> Option<Summary> {
+        let spread = m.spread.as_ref()?;
+        match self {
+            Metric::Latency => Some(spread.latency),
+            Metric::Throughput => spread.ops_per_sec,
+            _ => None,
+        }
+    }
+
+    /// Whether a bigger value is an improvement, as for rates.
+    pub fn higher_is_better(&self) -> bool {
+        matches!(
+            self,
+            Metric::Throughput
+                | Metric::ThreadThroughputMin
+                | Metric::ThreadThroughputMean
+                | Metric::ThreadThroughputMax
+        )
+    }
+
+    pub fn format(&self, value: f64) -> String {
+        match self {
+            Metric::WallTime => format!("{:.2} s", value),
+            Metric::Throughput
+            | Metric::ThreadThroughputMin
+            | Metric::ThreadThroughputMean
+            | Metric::ThreadThroughputMax => format_rate(value),
+            Metric::Cycles
+            | Metric::Instructions
+            | Metric::L1dMisses
+            | Metric::LlcMisses
+            | Metric::DtlbMisses
+            | Metric::RemoteAccesses => format!("{:.2}", value),
+            Metric::BytesPerEntry => format!("{:.0} B", value),
+            Metric::PeakBytes => format_bytes(value),
+            Metric::Allocations => format!("{:.0}", value),
+            _ => format!("{:.0} ns", value),
+        }
+    }
+}
+
+impl std::str::FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Metric::ALL.into_iter().find(|m| m.name() == s).ok_or_else(|| {
+            let names: Vec<_> = Metric::ALL.iter().map(|m| m.name()).collect();
+            format!("unknown metric '{}', expected one of {}", s, names.join(", "))
+        })
+    }
+}
+
+/// A way of charting a metric against a parameter such as the thread count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Chart {
+    /// A line per series
+    Lines,
+    /// A group of bars per parameter value, with a bar per series
+    Bars,
+    /// A row of cells per series, shaded by value
+    Heatmap,
+    /// Throughput of each series relative to its first point, usually one
+    /// thread
+    Speedup,
+}
+
+impl Chart {
+    pub const ALL: [Chart; 4] = [Chart::Lines, Chart::Bars, Chart::Heatmap, Chart::Speedup];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Chart::Lines => "lines",
+            Chart::Bars => "bars",
+            Chart::Heatmap => "heatmap",
+            Chart::Speedup => "speedup",
+        }
+    }
+}
+
+impl std::str::FromStr for Chart {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Chart::ALL.into_iter().find(|c| c.name() == s).ok_or_else(|| {
+            let names: Vec<_> = Chart::ALL.iter().map(|c| c.name()).collect();
+            format!("unknown chart '{}', expected one of {}", s, names.join(", "))
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scale {
+    #[default]
+    Linear,
+    Log,
+}
+
+/// The parameter a chart is drawn against.
+#[derive(Debug, Clone, Copy)]
+pub struct XAxis<'a> {
+    pub label: &'a str,
+    pub scale: Scale,
+}
+
+impl<'a> XAxis<'a> {
+    pub fn linear(label: &'a str) -> Self {
+        XAxis { label, scale: Scale::Linear }
+    }
+
+    /// For sweeps over sizes that grow by multiples.
+    pub fn log(label: &'a str) -> Self {
+        XAxis { label, scale: Scale::Log }
+    }
+}
+
+fn format_rate(ops_per_sec: f64) -> String {
+    if ops_per_sec >= 1e9 {
+        format!("{:.1}G/s", ops_per_sec / 1e9)
+    } else if ops_per_sec >= 1e6 {
+        format!("{:.1}M/s", ops_per_sec / 1e6)
+    } else if ops_per_sec >= 1e3 {
+        format!("{:.1}K/s", ops_per_sec / 1e3)
+    } else {
+        format!("{:.0}/s", ops_per_sec)
+    }
+}
+
+fn format_bytes(bytes: f64) -> String {
+    if bytes >= 1024.0 * 1024.0 * 1024.0 {
+        format!("{:.1} GB", bytes / (1024.0 * 1024.0 * 1024.0))
+    } else if bytes >= 1024.0 * 1024.0 {
+        format!("{:.1} MB", bytes / (1024.0 * 1024.0))
+    } else if bytes >= 1024.0 {
+        format!("{:.1} KB", bytes / 1024.0)
+    } else {
+        format!("{:.0} B", bytes)
+    }
+}
+
+const FONT: &str = "Fira Code";
+const PLOT_WIDTH: u32 = 800;
+const PLOT_HEIGHT: u32 = 400;
+
+/// Draws the line of a series in its style, with a marker at every point if
+/// it has one, and adds it to the legend.
+fn draw_series<'a, X, Y>(
+    chart: &mut ChartContext<'a, SVGBackend<'a>, Cartesian2d<X, Y>>,
+    name: &str,
+    points: Vec<(X::ValueType, Y::ValueType)>,
+    style: Style,
+) -> Result<(), Box<dyn Error>>
+where
+    X: Ranged,
+    Y: Ranged,
+    X::ValueType: Clone + 'static,
+    Y::ValueType: Clone + 'static,
+{
+    let color = style.color;
+    let at = || points.iter().cloned();
+    match style.marker {
+        None => {}
+        Some(Marker::Circle) => {
+            chart.draw_series(at().map(|p| Circle::new(p, 3, color.filled())))?;
+        }
+        Some(Marker::Triangle) => {
+            chart.draw_series(at().map(|p| TriangleMarker::new(p, 4, color.filled())))?;
+        }
+        Some(Marker::Cross) => {
+            chart.draw_series(at().map(|p| Cross::new(p, 3, color)))?;
+        }
+        Some(Marker::Ring) => {
+            chart.draw_series(at().map(|p| Circle::new(p, 3, color)))?;
+        }
+    }
+
+    let series = match style.dash.pattern() {
+        None => chart.draw_series(LineSeries::new(points, color))?,
+        Some((dash, gap)) => chart.draw_series(DashedLineSeries::new(points, dash, gap, color.into()))?,
+    };
+    series.label(name).legend(move |(x, y)| {
+        let (dash, gap) = style.dash.pattern().unwrap_or((20, 0));
+        EmptyElement::at((x, y))
+            + DashedPathElement::new(vec![(0, 0), (20, 0)], dash, gap, color)
+            + PathElement::new(marker_outline(style.marker), color)
+    });
+    Ok(())
+}
+
+/// The marker of a legend entry as a path around the middle of its line.
+fn marker_outline(marker: Option<Marker>) -> Vec<(i32, i32)> {
+    match marker {
+        None => Vec::new(),
+        Some(Marker::Circle | Marker::Ring) => vec![(7, -2), (8, -3), (12, -3), (13, -2), (13, 2), (12, 3), (8, 3), (7, 2), (7, -2)],
+        Some(Marker::Triangle) => vec![(10, -4), (14, 3), (6, 3), (10, -4)],
+        Some(Marker::Cross) => vec![(7, -3), (13, 3), (10, 0), (7, 3), (13, -3)],
+    }
+}
+
+/// Writes one chart per metric and kind of chart. Latency line charts are
+/// written to `file`, other metrics get their name added, e.g.
+/// `latency99-10000-p99.svg`, and other kinds of chart theirs, e.g.
+/// `latency99-10000-bars-p99.svg`. Metrics that none of the records have are
+/// skipped.
+pub fn write_plots(
+    records: &[Measurement],
+    metrics: &[Metric],
+    charts: &[Chart],
+    caption: &str,
+    x_axis: XAxis,
+    out_dir: &Path,
+    file: &str,
+) -> Result<(), Box<dyn Error>> {
+    // A line through a single point can't be seen
+    let xs: BTreeSet<u64> = records.iter().map(|r| r.x()).collect();
+    let single = xs.len() == 1;
+
+    for &metric in metrics {
+        // Hardware counters in particular may not be available at all
+        if records.iter().all(|r| metric.value(r).is_none()) {
+            eprintln!("Skipping the {} chart for {}, nothing measured it", metric.name(), file);
+            continue;
+        }
+        for &chart in charts {
+            let file = match chart {
+                Chart::Lines => file.to_string(),
+                _ => with_suffix(file, chart.name()),
+            };
+            let path = chart_path(out_dir, &file, metric);
+            match chart {
+                Chart::Lines if single => write_bar_plot(records, metric, caption, x_axis.label, &path)?,
+                Chart::Lines => write_plot(records, metric, caption, x_axis, &path)?,
+                Chart::Bars => write_bar_plot(records, metric, caption, x_axis.label, &path)?,
+                Chart::Heatmap => {
+                    let mut rows: BTreeMap<&str, Vec<(u64, f64)>> = BTreeMap::new();
+                    for record in records {
+                        if let Some(value) = metric.value(record) {
+                            rows.entry(record.name).or_default().push((record.x(), value));
+                        }
+                    }
+                    let rows = rows.into_iter().map(|(name, cells)| (name.to_string(), cells)).collect();
+                    write_heatmap(rows, metric, caption, x_axis.label, "", &path)?;
+                }
+                Chart::Speedup if single => {
+                    eprintln!("Skipping {}, there is only one {} to compare", path.display(), x_axis.label);
+                }
+                Chart::Speedup => match write_speedup_plot(records, metric, caption, x_axis, &path) {
+                    Ok(()) => {}
+                    Err(e) => eprintln!("Skipping {}, {}", path.display(), e),
+                },
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn chart_path(out_dir: &Path, file: &str, metric: Metric) -> PathBuf {
+    if metric == Metric::Latency {
+        return out_dir.join(file);
+    }
+    out_dir.join(with_suffix(file, metric.name()))
+}
+
+/// Adds to the file stem, e.g. `latency99-10000.svg` to
+/// `latency99-10000-p99.svg`.
+fn with_suffix(file: &str, suffix: &str) -> String {
+    let file = Path::new(file);
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = file.extension().unwrap_or_default().to_string_lossy();
+    format!("{}-{}.{}", stem, suffix, extension)
+}
+
+/// Charts one metric of each named series against the thread count.
+/// Measurements that don't have the metric, such as imported results that
+/// only have a latency, are left out.
+pub fn write_plot(
+    records: &[Measurement],
+    metric: Metric,
+    caption: &str,
+    x_axis: XAxis,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let mut groups: BTreeMap<&str, Vec<(u64, f64)>> = BTreeMap::new();
+    let mut bands: BTreeMap<&str, Vec<(u64, f64, f64)>> = BTreeMap::new();
+
+    for record in records.iter() {
+        if let Some(value) = metric.value(record) {
+            let group = groups.entry(record.name).or_default();
+            group.push((record.x(), value));
+        }
+        if let Some((low, high)) = metric.band(record) {
+            let band = bands.entry(record.name).or_default();
+            band.push((record.x(), low, high));
+        }
+    }
+
+    if groups.is_empty() {
+        return Err(format!("no {} results to chart in {}", metric.name(), path.display()).into());
+    }
+    let y_axis = Axis { label: metric.label(), format: &|v| metric.format(v) };
+    write_lines(Lines { groups, bands, reference: None }, caption, x_axis, y_axis, path)
+}
+
+/// Charts how much more throughput each series gets than at its first
+/// point, from the throughput where it was measured or otherwise from the
+/// latency per op of each thread. Other metrics don't scale with threads.
+pub fn write_speedup_plot(
+    records: &[Measurement],
+    metric: Metric,
+    caption: &str,
+    x_axis: XAxis,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let rate = |m: &Measurement| -> Option<f64> {
+        match metric {
+            Metric::Latency => Some(m.thread_count as f64 / m.latency),
+            Metric::Throughput => m.throughput.map(|t| t.ops_per_sec),
+            _ => None,
+        }
+    };
+
+    let mut rates: BTreeMap<&str, Vec<(u64, f64)>> = BTreeMap::new();
+    for record in records {
+        if let Some(rate) = rate(record).filter(|r| r.is_finite() && *r > 0.0) {
+            rates.entry(record.name).or_default().push((record.thread_count, rate));
+        }
+    }
+    if rates.is_empty() {
+        return Err(format!("no speedup from {}", metric.name()).into());
+    }
+
+    // Speedup is relative to one thread, so a series without a single thread
+    // point has nothing to scale from
+    let mut groups: BTreeMap<&str, Vec<(u64, f64)>> = BTreeMap::new();
+    for (name, mut points) in rates {
+        let Some(&(_, base)) = points.iter().find(|p| p.0 == 1) else {
+            eprintln!("Leaving {} out of {}, it has no single thread result", name, path.display());
+            continue;
+        };
+        points.sort_by_key(|p| p.0);
+        groups.insert(name, points.into_iter().map(|(x, rate)| (x, rate / base)).collect());
+    }
+    if groups.is_empty() {
+        return Err("no series has a single thread result".into());
+    }
+
+    // Perfect scaling from one thread
+    let xs: BTreeSet<u64> = groups.values().flatten().map(|p| p.0).collect();
+    let reference = xs.iter().map(|&x| (x, x as f64)).collect();
+
+    let caption = format!("{} (speedup)", caption);
+    let y_axis = Axis { label: "Speedup", format: &|v| format!("{:.1}x", v) };
+    let lines = Lines { groups, bands: BTreeMap::new(), reference: Some(("linear", reference)) };
+    write_lines(lines, &caption, x_axis, y_axis, path)
+}
+
+/// The series of a line chart.
+struct Lines<'a> {
+    groups: BTreeMap<&'a str, Vec<(u64, f64)>>,
+    /// Confidence bands, drawn under the lines.
+    bands: BTreeMap<&'a str, Vec<(u64, f64, f64)>>,
+    /// A line to compare the series against, drawn in grey.
+    reference: Option<(&'a str, Vec<(u64, f64)>)>,
+}
+
+fn write_lines(lines: Lines, caption: &str, x_axis: XAxis, y_axis: Axis, path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut points: Vec<(u64, f64)> = lines.groups.values().flatten().copied().collect();
+    for &(x, low, high) in lines.bands.values().flatten() {
+        points.push((x, low));
+        points.push((x, high));
+    }
+    if let Some((_, reference)) = &lines.reference {
+        points.extend(reference);
+    }
+
+    let resolution = (PLOT_WIDTH, PLOT_HEIGHT);
+    let root = SVGBackend::new(path, resolution).into_drawing_area();
+
+    root.fill(&WHITE)?;
+
+
+    let y_min = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+    let y_max = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+    let y_diff = y_max - y_min;
+    let y_padding = (y_diff / 10.0).min(y_min);
+    let y_range = y_min - y_padding..y_max + y_padding;
+
+    let x_min = points.iter().map(|p| p.0).min().unwrap();
+    let x_max = points.iter().map(|p| p.0).max().unwrap();
+
+
+    let mut builder = ChartBuilder::on(&root);
+    builder
+        .margin(10)
+        .caption(format!("{} ({} alloc)", caption, allocator::NAME), (FONT, 20))
+        .set_label_area_size(LabelAreaPosition::Left, 70)
+        .set_label_area_size(LabelAreaPosition::Right, 70)
+        .set_label_area_size(LabelAreaPosition::Bottom, 40);
+
+    match x_axis.scale {
+        Scale::Linear => {
+            let x_min = x_min.min(1);
+            let chart = builder.build_cartesian_2d(x_min..x_max.max(x_min + 1), y_range)?;
+            draw_lines(chart, lines, x_axis, y_axis)
+        }
+        Scale::Log => {
+            // Zero has no place on a log axis
+            let x_min = x_min.max(1);
+            let chart = builder.build_cartesian_2d((x_min..x_max.max(x_min + 1)).log_scale(), y_range)?;
+            draw_lines(chart, lines, x_axis, y_axis)
+        }
+    }
+}
+
+fn draw_lines<'a, X>(
+    mut chart: ChartContext<'a, SVGBackend<'a>, Cartesian2d<X, RangedCoordf64>>,
+    lines: Lines,
+    x_axis: XAxis,
+    y_axis: Axis,
+) -> Result<(), Box<dyn Error>>
+where
+    X: Ranged<ValueType = u64, FormatOption = DefaultFormatting> + ValueFormatter<u64>,
+{
+    chart
+        .configure_mesh()
+        .disable_y_mesh()
+        .x_label_formatter(&|v| format!("{}", v))
+        .y_label_formatter(&|v| (y_axis.format)(*v))
+        .x_labels(20)
+        .y_labels(20)
+        .y_desc(y_axis.label)
+        .x_desc(x_axis.label)
+        .draw()?;
+
+    let styles = palette::styles(lines.groups.keys().chain(lines.bands.keys()).copied());
+
+    // The confidence bands go underneath all the lines
+    for (name, band) in lines.bands {
+        let color = styles[name].color;
+        let outline: Vec<(u64, f64)> = band
+            .iter()
+            .map(|&(x, _, high)| (x, high))
+            .chain(band.iter().rev().map(|&(x, low, _)| (x, low)))
+            .collect();
+        chart.draw_series(std::iter::once(Polygon::new(outline, color.mix(0.15).filled())))?;
+    }
+
+    if let Some((name, points)) = lines.reference {
+        let style = Style { color: RGBColor(160, 160, 160), dash: Dash::Dashed, marker: None };
+        draw_series(&mut chart, name, points, style)?;
+    }
+
+    for (name, points) in lines.groups {
+        draw_series(&mut chart, name, points, styles[name])?;
+    }
+
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .label_font((FONT, 13))
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    Ok(())
+}
+
+/// Charts one metric of each series as a group of bars per parameter value,
+/// with the confidence interval marked on bars that have one. Suits
+/// comparisons at a single thread count or size.
+pub fn write_bar_plot(
+    records: &[Measurement],
+    metric: Metric,
+    caption: &str,
+    x_label: &str,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    // The x value, the metric and its confidence interval
+    type Bar = (u64, f64, Option<(f64, f64)>);
+    let mut bars: BTreeMap<&str, Vec<Bar>> = BTreeMap::new();
+    for record in records {
+        if let Some(value) = metric.value(record) {
+            bars.entry(record.name).or_default().push((record.x(), value, metric.band(record)));
+        }
+    }
+    if bars.is_empty() {
+        return Err(format!("no {} results to chart in {}", metric.name(), path.display()).into());
+    }
+
+    let xs: Vec<u64> = bars.values().flatten().map(|b| b.0).collect::<BTreeSet<_>>().into_iter().collect();
+    let y_max = bars
+        .values()
+        .flatten()
+        .map(|&(_, value, band)| band.map_or(value, |b| b.1.max(value)))
+        .fold(0.0, f64::max);
+
+    let root = SVGBackend::new(path, (PLOT_WIDTH, PLOT_HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    // Group i is centered on i, where the labels go
+    let mut chart = ChartBuilder::on(&root)
+        .margin(10)
+        .caption(format!("{} ({} alloc)", caption, allocator::NAME), (FONT, 20))
+        .set_label_area_size(LabelAreaPosition::Left, 70)
+        .set_label_area_size(LabelAreaPosition::Right, 70)
+        .set_label_area_size(LabelAreaPosition::Bottom, 40)
+        .build_cartesian_2d(-0.5..xs.len() as f64 - 0.5, 0.0..y_max * 1.1)?;
+
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .disable_y_mesh()
+        .x_label_formatter(&|v| category(&xs, *v).map(|x| x.to_string()).unwrap_or_default())
+        .y_label_formatter(&|v| metric.format(*v))
+        .x_labels(xs.len())
+        .y_labels(20)
+        .y_desc(metric.label())
+        .x_desc(x_label)
+        .draw()?;
+
+    let styles = palette::styles(bars.keys().copied());
+    let width = 0.8 / bars.len() as f64;
+    for (i, (name, bars)) in bars.into_iter().enumerate() {
+        let color = styles[name].color;
+        let left = |x: u64| xs.iter().position(|&v| v == x).unwrap() as f64 - 0.4 + i as f64 * width;
+        chart
+            .draw_series(bars.iter().map(|&(x, value, _)| {
+                Rectangle::new([(left(x), 0.0), (left(x) + width, value)], color.filled())
+            }))?
+            .label(name)
+            .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], color.filled()));
+
+        let whiskers = bars.iter().filter_map(|&(x, _, band)| {
+            let (low, high) = band?;
+            let middle = left(x) + width / 2.0;
+            Some(PathElement::new(vec![(middle, low), (middle, high)], BLACK))
+        });
+        chart.draw_series(whiskers)?;
+    }
+
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .label_font((FONT, 13))
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    Ok(())
+}
+
+/// Charts one metric over two parameters, a row of cells per row label and
+/// a column per x value, shaded from the best value to the worst.
+pub fn write_heatmap(
+    rows: Vec<(String, Vec<(u64, f64)>)>,
+    metric: Metric,
+    caption: &str,
+    x_label: &str,
+    y_label: &str,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let values: Vec<f64> = rows.iter().flat_map(|(_, cells)| cells.iter().map(|c| c.1)).collect();
+    if values.is_empty() {
+        return Err(format!("no {} results to chart in {}", metric.name(), path.display()).into());
+    }
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let xs: Vec<u64> = rows
+        .iter()
+        .flat_map(|(_, cells)| cells.iter().map(|c| c.0))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    let root = SVGBackend::new(path, (PLOT_WIDTH, PLOT_HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    // Cells are centered on whole numbers, where the labels go, and the
+    // first row is drawn at the top
+    let row_count = rows.len();
+    let row_labels: Vec<&str> = rows.iter().rev().map(|r| r.0.as_str()).collect();
+    let mut chart = ChartBuilder::on(&root)
+        .margin(10)
+        .caption(format!("{} ({}, {} alloc)", caption, metric.label(), allocator::NAME), (FONT, 20))
+        .set_label_area_size(LabelAreaPosition::Left, 140)
+        .set_label_area_size(LabelAreaPosition::Bottom, 40)
+        .build_cartesian_2d(-0.5..xs.len() as f64 - 0.5, -0.5..row_count as f64 - 0.5)?;
+
+    chart
+        .configure_mesh()
+        .disable_mesh()
+        .x_label_formatter(&|v| category(&xs, *v).map(|x| x.to_string()).unwrap_or_default())
+        .y_label_formatter(&|v| category(&row_labels, *v).map(str::to_string).unwrap_or_default())
+        .x_labels(xs.len())
+        .y_labels(row_count)
+        .x_desc(x_label)
+        .y_desc(y_label)
+        .draw()?;
+
+    let text = TextStyle::from((FONT, 11).into_font()).pos(Pos::new(HPos::Center, VPos::Center));
+    for (row, (_, cells)) in rows.iter().enumerate() {
+        let y = (row_count - row - 1) as f64;
+        for &(x, value) in cells {
+            let x = xs.iter().position(|&v| v == x).unwrap() as f64;
+            let mut badness = if max > min { (value - min) / (max - min) } else { 0.0 };
+            if metric.higher_is_better() {
+                badness = 1.0 - badness;
+            }
+            chart.draw_series(std::iter::once(Rectangle::new(
+                [(x - 0.5, y - 0.5), (x + 0.5, y + 0.5)],
+                heat_color(badness).filled(),
+            )))?;
+            chart.draw_series(std::iter::once(Text::new(metric.format(value), (x, y), text.clone())))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The category at a whole number position on an axis of categories.
+fn category<T: Copy>(categories: &[T], position: f64) -> Option<T> {
+    if position.fract() != 0.0 || position < 0.0 {
+        return None;
+    }
+    categories.get(position as usize).copied()
+}
+
+/// Pale yellow for the best values to red for the worst.
+fn heat_color(badness: f64) -> RGBColor {
+    let mix = |from: u8, to: u8| (from as f64 + (to as f64 - from as f64) * badness).round() as u8;
+    RGBColor(mix(255, 215), mix(245, 48), mix(190, 39))
+}
+
+
+/// Writes a throughput over time chart per thread count, named after `file`,
+/// e.g. `latency99-10000-timeline-8.svg`.
+pub fn write_timeline_plots(timelines: &[Timeline], caption: &str, out_dir: &Path, file: &str) -> Result<(), Box<dyn Error>> {
+    let mut by_threads: BTreeMap<u64, Vec<Timeline>> = BTreeMap::new();
+    for timeline in timelines {
+        by_threads.entry(timeline.thread_count).or_default().push(timeline.clone());
+    }
+
+    let file = Path::new(file);
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = file.extension().unwrap_or_default().to_string_lossy();
+    for (threads, timelines) in by_threads {
+        let path = out_dir.join(format!("{}-timeline-{}.{}", stem, threads, extension));
+        write_timeline_plot(&timelines, &format!("{} {} threads", caption, threads), &path)?;
+    }
+    Ok(())
+}
+
+/// Charts the throughput over time of each timeline, to show stalls such as
+/// a map resizing.
+pub fn write_timeline_plot(timelines: &[Timeline], caption: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+    let series: Vec<(&str, Vec<(f64, f64)>)> = timelines
+        .iter()
+        .map(|t| (t.name.as_str(), t.throughput()))
+        .collect();
+
+    let x_axis = Axis { label: "Seconds", format: &|v| format!("{:.2}", v) };
+    let y_axis = Axis { label: Metric::Throughput.label(), format: &format_rate };
+    write_curves(series, caption, x_axis, y_axis, SeriesLabelPosition::UpperRight, path)
+}
+
+/// Writes a latency against achieved throughput chart per metric and thread
+/// count for open loop runs, named after `file`, e.g.
+/// `latency99-10000-rate8.svg` and `latency99-10000-rate8-p99.svg`.
+pub fn write_rate_plots(
+    records: &[Measurement],
+    metrics: &[Metric],
+    caption: &str,
+    out_dir: &Path,
+    file: &str,
+) -> Result<(), Box<dyn Error>> {
+    // Imported results have no throughput to chart against
+    let mut by_threads: BTreeMap<u64, Vec<&Measurement>> = BTreeMap::new();
+    for record in records.iter().filter(|r| r.throughput.is_some()) {
+        by_threads.entry(record.thread_count).or_default().push(record);
+    }
+
+    let file = Path::new(file);
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = file.extension().unwrap_or_default().to_string_lossy();
+    for (threads, records) in by_threads {
+        let rate_file = format!("{}-rate{}.{}", stem, threads, extension);
+        for &metric in metrics {
+            let mut groups: BTreeMap<&str, Vec<(f64, f64)>> = BTreeMap::new();
+            for record in &records {
+                let throughput = record.throughput.map(|t| t.ops_per_sec);
+                if let (Some(x), Some(y)) = (throughput, metric.value(record)) {
+                    groups.entry(record.name).or_default().push((x, y));
+                }
+            }
+            if groups.is_empty() {
+                eprintln!("Skipping the {} chart for {}, nothing measured it", metric.name(), rate_file);
+                continue;
+            }
+
+            let series = groups
+                .into_iter()
+                .map(|(name, mut points)| {
+                    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+                    (name, points)
+                })
+                .collect();
+            let x_axis = Axis { label: "Achieved throughput", format: &format_rate };
+            let y_axis = Axis { label: metric.label(), format: &|v| metric.format(v) };
+            write_curves(
+                series,
+                &format!("{} {} threads", caption, threads),
+                x_axis,
+                y_axis,
+                SeriesLabelPosition::UpperLeft,
+                &chart_path(out_dir, &rate_file, metric),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+struct Axis<'a> {
+    label: &'a str,
+    format: &'a dyn Fn(f64) -> String,
+}
+
+/// Draws each named series of (x, y) points as a line, both axes from zero.
+fn write_curves(
+    series: Vec<(&str, Vec<(f64, f64)>)>,
+    caption: &str,
+    x_axis: Axis,
+    y_axis: Axis,
+    legend: SeriesLabelPosition,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let series: Vec<_> = series.into_iter().filter(|(_, points)| !points.is_empty()).collect();
+
+    let points: Vec<(f64, f64)> = series.iter().flat_map(|(_, p)| p).copied().collect();
+    if points.is_empty() {
+        return Err(format!("no points to chart in {}", path.display()).into());
+    }
+
+    let x_max = points.iter().map(|p| p.0).fold(0.0, f64::max);
+    let y_max = points.iter().map(|p| p.1).fold(0.0, f64::max);
+
+    let root = SVGBackend::new(path, (PLOT_WIDTH, PLOT_HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .margin(10)
+        .caption(format!("{} ({} alloc)", caption, allocator::NAME), (FONT, 20))
+        .set_label_area_size(LabelAreaPosition::Left, 70)
+        .set_label_area_size(LabelAreaPosition::Right, 70)
+        .set_label_area_size(LabelAreaPosition::Bottom, 40)
+        .build_cartesian_2d(0.0..x_max * 1.05, 0.0..y_max * 1.1)?;
+
+    chart
+        .configure_mesh()
+        .disable_y_mesh()
+        .x_label_formatter(&|v| (x_axis.format)(*v))
+        .y_label_formatter(&|v| (y_axis.format)(*v))
+        .x_labels(20)
+        .y_labels(20)
+        .y_desc(y_axis.label)
+        .x_desc(x_axis.label)
+        .draw()?;
+
+    let styles = palette::styles(series.iter().map(|(name, _)| *name));
+    for (name, points) in series {
+        draw_series(&mut chart, name, points, styles[name])?;
+    }
+
+    chart
+        .configure_series_labels()
+        .position(legend)
+        .label_font((FONT, 13))
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    Ok(())
+}
+
+/// Charts the percentage change of each series against the thread count,
+/// with the regression threshold marked either side of zero.
+pub fn write_diff_plot(
+    series: Vec<(&str, Vec<(f64, f64)>)>,
+    caption: &str,
+    threshold: f64,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let points: Vec<(f64, f64)> = series.iter().flat_map(|(_, p)| p).copied().collect();
+    if points.is_empty() {
+        return Err(format!("no points to chart in {}", path.display()).into());
+    }
+
+    let x_max = points.iter().map(|p| p.0).fold(0.0, f64::max);
+    let y_max = points.iter().map(|p| p.1).fold(threshold, f64::max);
+    let y_min = points.iter().map(|p| p.1).fold(-threshold, f64::min);
+    let y_pad = (y_max - y_min) * 0.1;
+
+    let root = SVGBackend::new(path, (PLOT_WIDTH, PLOT_HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .margin(10)
+        .caption(caption, (FONT, 20))
+        .set_label_area_size(LabelAreaPosition::Left, 70)
+        .set_label_area_size(LabelAreaPosition::Right, 70)
+        .set_label_area_size(LabelAreaPosition::Bottom, 40)
+        .build_cartesian_2d(0.0..x_max * 1.05, (y_min - y_pad)..(y_max + y_pad))?;
+
+    chart
+        .configure_mesh()
+        .disable_y_mesh()
+        .x_label_formatter(&|v| format!("{:.0}", v))
+        .y_label_formatter(&|v| format!("{:+.0}%", v))
+        .x_labels(20)
+        .y_labels(20)
+        .y_desc("Change from baseline")
+        .x_desc("Threads")
+        .draw()?;
+
+    chart.draw_series(LineSeries::new([(0.0, 0.0), (x_max * 1.05, 0.0)], BLACK))?;
+    for limit in [threshold, -threshold] {
+        chart.draw_series(LineSeries::new([(0.0, limit), (x_max * 1.05, limit)], RED.mix(0.4)))?;
+    }
+
+    // Every point is marked, as each is a separate comparison
+    let styles = palette::styles(series.iter().map(|(name, _)| *name));
+    for (name, points) in series {
+        let style = styles[name];
+        let style = Style { marker: Some(style.marker.unwrap_or(Marker::Circle)), ..style };
+        draw_series(&mut chart, name, points, style)?;
+    }
+
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .label_font((FONT, 13))
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chart_files_and_categories() {
+        assert_eq!("speedup".parse::<Chart>(), Ok(Chart::Speedup));
+        assert!("pie".parse::<Chart>().is_err());
+
+        let out_dir = Path::new("out");
+        assert_eq!(chart_path(out_dir, "keys99-1000.svg", Metric::Latency), out_dir.join("keys99-1000.svg"));
+        let bars = with_suffix("keys99-1000.svg", Chart::Bars.name());
+        assert_eq!(chart_path(out_dir, &bars, Metric::P99), out_dir.join("keys99-1000-bars-p99.svg"));
+
+        let threads = [1, 2, 4];
+        assert_eq!(category(&threads, 2.0), Some(4));
+        assert_eq!(category(&threads, 0.5), None);
+        assert_eq!(category(&threads, -1.0), None);
+        assert_eq!(category(&threads, 3.0), None);
+    }
+}
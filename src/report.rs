@@ -0,0 +1,319 @@
+// One HTML file of a run: every chart in the output directory, the settings
+// and numbers behind each, and the machine it ran on, so a run can be shared
+// or archived as a single file.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::perf_info::MachineInfo;
+use crate::plot::Metric;
+use crate::results::{Record, Results, RunInfo};
+
+const STYLE: &str = r#"
+body { font-family: sans-serif; margin: 2em auto; max-width: 1000px; color: #222; }
+h2 { border-bottom: 1px solid #ccc; margin-top: 2em; }
+table { border-collapse: collapse; margin: 1em 0; font-size: 13px; }
+th, td { border: 1px solid #ddd; padding: 3px 8px; text-align: right; }
+th { background: #f4f4f4; cursor: pointer; user-select: none; }
+td:first-child, th:first-child { text-align: left; }
+svg { display: block; margin: 1em 0; max-width: 100%; height: auto; }
+details { margin: 1em 0; }
+"#;
+
+/// Sorts a table by the column clicked, by number where the cells have one.
+const SCRIPT: &str = r#"
+document.querySelectorAll("table.sortable th").forEach(th => th.addEventListener("click", () => {
+    const body = th.closest("table").tBodies[0];
+    const column = th.cellIndex;
+    const ascending = th.dataset.order !== "asc";
+    th.dataset.order = ascending ? "asc" : "desc";
+    const key = cell => {
+        const text = cell.dataset.value ?? cell.textContent;
+        const number = parseFloat(text);
+        return isNaN(number) ? text : number;
+    };
+    const rows = [...body.rows].sort((a, b) => {
+        const x = key(a.cells[column]), y = key(b.cells[column]);
+        return (x < y ? -1 : x > y ? 1 : 0) * (ascending ? 1 : -1);
+    });
+    rows.forEach(row => body.appendChild(row));
+}));
+"#;
+
+/// Writes `report.html` to the output directory from the charts and result
+/// files in it.
+pub fn write_report(out_dir: &Path) -> Result<PathBuf, String> {
+    let results = result_files(out_dir)?;
+    let charts = files_with_extension(out_dir, "svg")?;
+
+    // Each chart goes with the result file whose name it starts with,
+    // the longest if several do, e.g. `latency99-10000-p99.svg`
+    let stems: Vec<String> = results.iter().map(|(path, _)| stem(path)).collect();
+    let mut owned: Vec<Vec<&PathBuf>> = vec![Vec::new(); results.len()];
+    let mut loose = Vec::new();
+    for chart in &charts {
+        let name = stem(chart);
+        let owner = stems
+            .iter()
+            .enumerate()
+            .filter(|(_, stem)| name == **stem || name.starts_with(&format!("{}-", stem)))
+            .max_by_key(|(_, stem)| stem.len());
+        match owner {
+            Some((i, _)) => owned[i].push(chart),
+            None => loose.push(chart),
+        }
+    }
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>map-bench report</title>\n");
+    let _ = writeln!(html, "<style>{}</style>\n</head>\n<body>\n<h1>map-bench report</h1>", STYLE);
+
+    let info = results.first().map(|(_, r)| &r.info);
+    if let Some(info) = info {
+        html.push_str(&run_table(info));
+    }
+    let machine = info.and_then(|i| i.machine.clone()).or_else(|| load_machine_info(out_dir));
+    if let Some(machine) = &machine {
+        html.push_str(&machine_section(machine));
+    }
+
+    for ((path, results), charts) in results.iter().zip(&owned) {
+        let _ = writeln!(html, "<h2 id=\"{0}\">{0}</h2>", escape(&stem(path)));
+        for chart in charts {
+            html.push_str(&inline_svg(chart)?);
+        }
+        html.push_str(&settings_table(&results.config));
+        html.push_str(&records_table(&results.records));
+    }
+
+    if !loose.is_empty() {
+        html.push_str("<h2>Other charts</h2>\n");
+        for chart in loose {
+            html.push_str(&inline_svg(chart)?);
+        }
+    }
+
+    let _ = writeln!(html, "<script>{}</script>\n</body>\n</html>", SCRIPT);
+
+    let path = out_dir.join("report.html");
+    std::fs::write(&path, html).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+fn files_with_extension(dir: &Path, extension: &str) -> Result<Vec<PathBuf>, String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|e| e == extension))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// The result files in the directory. Other json, such as the machine info,
+/// is left out.
+fn result_files(dir: &Path) -> Result<Vec<(PathBuf, Results)>, String> {
+    Ok(files_with_extension(dir, "json")?
+        .into_iter()
+        .filter_map(|path| Results::load(&path).ok().map(|r| (path, r)))
+        .collect())
+}
+
+fn load_machine_info(dir: &Path) -> Option<MachineInfo> {
+    let text = std::fs::read_to_string(dir.join("processor.info.json")).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+fn stem(path: &Path) -> String {
+    path.file_stem().unwrap_or_default().to_string_lossy().to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn inline_svg(path: &Path) -> Result<String, String> {
+    let svg = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    // An xml declaration isn't allowed in the middle of a document
+    let start = svg.find("<svg").unwrap_or(0);
+    Ok(format!("{}\n", &svg[start..]))
+}
+
+fn run_table(info: &RunInfo) -> String {
+    let mut html = String::from("<table>\n");
+    let rows = [
+        ("Started", Some(info.timestamp.as_str())),
+        ("Commit", info.git_commit.as_deref()),
+        ("Compiler", info.rustc.as_deref()),
+        ("Allocator", Some(info.allocator.as_str())),
+    ];
+    for (name, value) in rows {
+        let _ = writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", name, escape(value.unwrap_or("unknown")));
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+fn format_cpus(cpus: &[usize]) -> String {
+    cpus.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn machine_section(info: &MachineInfo) -> String {
+    let mut html = String::from("<h2>Machine</h2>\n");
+    let _ = writeln!(
+        html,
+        "<p>{} ({}): {} packages, {} cores, {} logical processors{}</p>",
+        escape(&info.cpu_model),
+        escape(&info.os),
+        info.packages.len(),
+        info.num_cores(),
+        info.logical_cpus,
+        if info.smt() { ", SMT" } else { "" }
+    );
+
+    if !info.numa_nodes.is_empty() {
+        html.push_str("<table>\n<tr><th>Numa node</th><th>Cpus</th><th>Memory</th><th>Distances</th></tr>\n");
+        for node in &info.numa_nodes {
+            let memory = node.memory_bytes.map(|m| format!("{} MB", m / (1024 * 1024))).unwrap_or_default();
+            let distances = node.distances.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(" ");
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                node.id,
+                format_cpus(&node.cpus),
+                memory,
+                distances
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    if !info.caches.is_empty() {
+        html.push_str("<table>\n<tr><th>Cache</th><th>Size</th><th>Line</th><th>Ways</th><th>Cpus</th></tr>\n");
+        for cache in &info.caches {
+            let ways = cache.ways.map_or("full".to_string(), |w| w.to_string());
+            let _ = writeln!(
+                html,
+                "<tr><td>L{} {}</td><td>{} KB</td><td>{} B</td><td>{}</td><td>{}</td></tr>",
+                cache.level,
+                escape(&cache.kind),
+                cache.size_bytes / 1024,
+                cache.line_size,
+                ways,
+                format_cpus(&cache.shared_cpus)
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("<details><summary>Cores</summary>\n<table>\n<tr><th>Package</th><th>Core</th><th>Cpus</th></tr>\n");
+    for package in &info.packages {
+        for core in &package.cores {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                package.id,
+                core.id,
+                format_cpus(&core.cpus)
+            );
+        }
+    }
+    html.push_str("</table>\n</details>\n");
+    html
+}
+
+/// The scenario or command line settings, one row per top level setting.
+fn settings_table(config: &serde_json::Value) -> String {
+    let Some(settings) = config.as_object().filter(|s| !s.is_empty()) else {
+        return String::new();
+    };
+
+    let mut html = String::from("<table>\n");
+    for (name, value) in settings {
+        let value = match value {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        let _ = writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", escape(name), escape(&value));
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+/// A row per point with a column per metric that any point has. Cells sort
+/// by their raw value rather than the formatted one.
+fn records_table(records: &[Record]) -> String {
+    let measurements: Vec<_> = records.iter().map(Record::measurement).collect();
+    let metrics: Vec<Metric> = Metric::ALL
+        .into_iter()
+        .filter(|m| measurements.iter().any(|r| m.value(r).is_some()))
+        .collect();
+
+    let mut html = String::from("<table class=\"sortable\">\n<thead><tr><th>Series</th><th>Key</th><th>Threads or size</th>");
+    for metric in &metrics {
+        let _ = write!(html, "<th>{}</th>", escape(metric.label()));
+    }
+    html.push_str("</tr></thead>\n<tbody>\n");
+
+    for (record, measurement) in records.iter().zip(&measurements) {
+        let key_type = record.key_type.map(|k| format!("{:?}", k)).unwrap_or_default();
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td>",
+            escape(&record.name),
+            key_type.to_lowercase(),
+            record.items.unwrap_or(record.thread_count)
+        );
+        for metric in &metrics {
+            match metric.value(measurement) {
+                Some(value) => {
+                    let _ = write!(html, "<td data-value=\"{}\">{}</td>", value, escape(&metric.format(value)));
+                }
+                None => html.push_str("<td></td>"),
+            }
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody>\n</table>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::perf::Measurement;
+
+    #[test]
+    fn test_report_groups_charts_by_result_file() {
+        let dir = std::env::temp_dir().join(format!("map-bench-report-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let measurement = Measurement::new("scc <new>", 12.5, 4);
+        let results = Results::new(&serde_json::json!({ "prefill": 1000 }), &[measurement]);
+        results.write(&dir, "memory-read.svg").unwrap();
+        results.write(&dir, "memory-read-write.svg").unwrap();
+        let svg = |title: &str| format!("<?xml version=\"1.0\"?>\n<svg><title>{}</title></svg>", title);
+        for chart in ["memory-read", "memory-read-p99", "memory-read-write", "heatmap-scc"] {
+            std::fs::write(dir.join(format!("{}.svg", chart)), svg(chart)).unwrap();
+        }
+
+        let html = std::fs::read_to_string(write_report(&dir).unwrap()).unwrap();
+        let section = |heading: &str| {
+            let start = html.find(heading).unwrap();
+            let end = html[start + 1..].find("<h2").map_or(html.len(), |i| start + 1 + i);
+            &html[start..end]
+        };
+        let read = section("id=\"memory-read\"");
+        assert!(read.contains("<title>memory-read<") && read.contains("<title>memory-read-p99<"));
+        assert!(!read.contains("<title>memory-read-write<"));
+        assert!(section("id=\"memory-read-write\"").contains("<title>memory-read-write<"));
+        assert!(section("Other charts").contains("<title>heatmap-scc<"));
+        assert!(!html.contains("<?xml"));
+        assert!(html.contains("scc &lt;new&gt;"));
+        assert!(html.contains("data-value=\"12.5\""));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
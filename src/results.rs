@@ -0,0 +1,311 @@
+// Result files: every chart is written along with a JSON and a CSV file of
+// the measurements behind it, so old runs can be re-plotted and compared
+// without running the benchmarks again.
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::allocator::{self, Footprint};
+use crate::histogram::Percentiles;
+use crate::perf::{Measurement, Spread, Throughput};
+use crate::perf_counters::Counts;
+use crate::perf_info::{self, MachineInfo};
+use crate::scenario::KeyType;
+
+/// Where and how the benchmarks were run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunInfo {
+    /// When the run started, in UTC.
+    pub timestamp: String,
+    pub git_commit: Option<String>,
+    pub rustc: Option<String>,
+    pub allocator: String,
+    pub machine: Option<MachineInfo>,
+}
+
+static RUN_INFO: OnceLock<RunInfo> = OnceLock::new();
+
+/// The info of this run, collected on the first call.
+pub fn run_info() -> &'static RunInfo {
+    RUN_INFO.get_or_init(|| RunInfo {
+        timestamp: format_timestamp(SystemTime::now()),
+        git_commit: option_env!("MAP_BENCH_GIT_COMMIT").map(str::to_string),
+        rustc: option_env!("MAP_BENCH_RUSTC").map(str::to_string),
+        allocator: allocator::NAME.to_string(),
+        machine: perf_info::machine_info().ok(),
+    })
+}
+
+/// One point of a chart, owning its name so it can be read back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub name: String,
+    /// The map measured, for map suites.
+    pub adapter: Option<String>,
+    pub key_type: Option<KeyType>,
+    pub thread_count: u64,
+    /// The map size, for suites charted by it.
+    pub items: Option<u64>,
+    /// The rate ops were scheduled at, for open loop runs.
+    pub target_rate: Option<f64>,
+    pub latency: f64,
+    pub percentiles: Option<Percentiles>,
+    pub throughput: Option<Throughput>,
+    pub spread: Option<Spread>,
+    pub counters: Option<Counts>,
+    pub footprint: Option<Footprint>,
+}
+
+impl From<&Measurement<'_>> for Record {
+    fn from(m: &Measurement) -> Self {
+        Record {
+            name: m.name.to_string(),
+            adapter: None,
+            key_type: None,
+            thread_count: m.thread_count,
+            items: m.items,
+            target_rate: m.target_rate,
+            latency: m.latency,
+            percentiles: m.percentiles,
+            throughput: m.throughput,
+            spread: m.spread,
+            counters: m.counters,
+            footprint: m.footprint,
+        }
+    }
+}
+
+impl Record {
+    pub fn measurement(&self) -> Measurement<'_> {
+        Measurement {
+            name: &self.name,
+            latency: self.latency,
+            thread_count: self.thread_count,
+            items: self.items,
+            target_rate: self.target_rate,
+            percentiles: self.percentiles,
+            throughput: self.throughput,
+            spread: self.spread,
+            counters: self.counters,
+            footprint: self.footprint,
+        }
+    }
+}
+
+/// The measurements behind one chart and the settings that produced them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Results {
+    pub info: RunInfo,
+    /// The scenario or command line settings of the suite.
+    pub config: serde_json::Value,
+    pub records: Vec<Record>,
+}
+
+impl Results {
+    pub fn new(config: &impl Serialize, measurements: &[Measurement]) -> Self {
+        Results {
+            info: run_info().clone(),
+            config: serde_json::to_value(config).expect("settings should convert to json"),
+            records: measurements.iter().map(Record::from).collect(),
+        }
+    }
+
+    /// Marks the records of shared maps, given the adapter and key type
+    /// behind each series name. Other series, such as imported ones, are
+    /// left unmarked.
+    pub fn mark_maps(mut self, map: impl Fn(&str) -> Option<(String, KeyType)>) -> Self {
+        for record in &mut self.records {
+            if let Some((adapter, key_type)) = map(&record.name) {
+                record.adapter = Some(adapter);
+                record.key_type = Some(key_type);
+            }
+        }
+        self
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&text).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    /// Writes `stem.json` and `stem.csv` to the output directory, named after
+    /// the chart file.
+    pub fn write(&self, out_dir: &Path, chart: &str) -> Result<(), String> {
+        let json_path = results_path(out_dir, chart, "json");
+        let file = File::create(&json_path).map_err(|e| format!("Failed to create {}: {}", json_path.display(), e))?;
+        serde_json::to_writer_pretty(file, self).map_err(|e| format!("Failed to write {}: {}", json_path.display(), e))?;
+
+        let csv_path = results_path(out_dir, chart, "csv");
+        self.write_csv(&csv_path)
+            .map_err(|e| format!("Failed to write {}: {}", csv_path.display(), e))
+    }
+
+    fn write_csv(&self, path: &Path) -> Result<(), csv::Error> {
+        let info = &self.info;
+        let config = self.config.to_string();
+        let mut writer = csv::Writer::from_path(path)?;
+        for record in &self.records {
+            let p = record.percentiles.unwrap_or_default();
+            let t = record.throughput;
+            let c = record.counters.unwrap_or_default();
+            let f = record.footprint;
+            writer.serialize(CsvRow {
+                timestamp: &info.timestamp,
+                git_commit: info.git_commit.as_deref(),
+                rustc: info.rustc.as_deref(),
+                allocator: &info.allocator,
+                cpu_model: info.machine.as_ref().map(|m| m.cpu_model.as_str()),
+                logical_cpus: info.machine.as_ref().map(|m| m.logical_cpus),
+                numa_nodes: info.machine.as_ref().map(|m| m.numa_nodes.len()),
+                config: &config,
+                name: &record.name,
+                adapter: record.adapter.as_deref(),
+                key_type: record.key_type,
+                thread_count: record.thread_count,
+                items: record.items,
+                target_rate: record.target_rate,
+                latency: record.latency,
+                p50: record.percentiles.map(|_| p.p50),
+                p90: record.percentiles.map(|_| p.p90),
+                p99: record.percentiles.map(|_| p.p99),
+                p999: record.percentiles.map(|_| p.p999),
+                max: record.percentiles.map(|_| p.max),
+                wall_secs: t.map(|t| t.wall_secs),
+                ops_per_sec: t.map(|t| t.ops_per_sec),
+                thread_ops_per_sec_min: t.map(|t| t.thread_ops_per_sec_min),
+                thread_ops_per_sec_mean: t.map(|t| t.thread_ops_per_sec_mean),
+                thread_ops_per_sec_max: t.map(|t| t.thread_ops_per_sec_max),
+                trials: record.spread.map(|s| s.trials),
+                rejected: record.spread.map(|s| s.rejected),
+                latency_ci_low: record.spread.map(|s| s.latency.ci_low),
+                latency_ci_high: record.spread.map(|s| s.latency.ci_high),
+                cycles: c.cycles,
+                instructions: c.instructions,
+                l1d_misses: c.l1d_misses,
+                llc_misses: c.llc_misses,
+                dtlb_misses: c.dtlb_misses,
+                remote_accesses: c.remote_accesses,
+                bytes_per_entry: f.map(|f| f.bytes_per_entry()),
+                peak_bytes: f.map(|f| f.after_run.peak_bytes),
+                allocations: f.map(|f| f.after_run.allocations),
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// One CSV line per record. The run info and config repeat on every line so
+/// files from several runs can be concatenated.
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    timestamp: &'a str,
+    git_commit: Option<&'a str>,
+    rustc: Option<&'a str>,
+    allocator: &'a str,
+    cpu_model: Option<&'a str>,
+    logical_cpus: Option<usize>,
+    numa_nodes: Option<usize>,
+    config: &'a str,
+    name: &'a str,
+    adapter: Option<&'a str>,
+    key_type: Option<KeyType>,
+    thread_count: u64,
+    items: Option<u64>,
+    target_rate: Option<f64>,
+    latency: f64,
+    p50: Option<f64>,
+    p90: Option<f64>,
+    p99: Option<f64>,
+    p999: Option<f64>,
+    max: Option<f64>,
+    wall_secs: Option<f64>,
+    ops_per_sec: Option<f64>,
+    thread_ops_per_sec_min: Option<f64>,
+    thread_ops_per_sec_mean: Option<f64>,
+    thread_ops_per_sec_max: Option<f64>,
+    trials: Option<usize>,
+    rejected: Option<usize>,
+    latency_ci_low: Option<f64>,
+    latency_ci_high: Option<f64>,
+    cycles: Option<f64>,
+    instructions: Option<f64>,
+    l1d_misses: Option<f64>,
+    llc_misses: Option<f64>,
+    dtlb_misses: Option<f64>,
+    remote_accesses: Option<f64>,
+    bytes_per_entry: Option<f64>,
+    peak_bytes: Option<isize>,
+    allocations: Option<usize>,
+}
+
+pub fn results_path(out_dir: &Path, chart: &str, extension: &str) -> PathBuf {
+    out_dir.join(Path::new(chart).with_extension(extension))
+}
+
+/// Formats a time as ISO 8601 in UTC, e.g. `2024-09-01T12:30:00Z`.
+fn format_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // Days since 1970-01-01 to a civil date, from Howard Hinnant's algorithm
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_format_timestamp() {
+        let at = |secs| format_timestamp(UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(at(0), "1970-01-01T00:00:00Z");
+        assert_eq!(at(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(at(1_725_193_800), "2024-09-01T12:30:00Z");
+    }
+
+    #[test]
+    fn test_results_round_trip() {
+        let measurement = Measurement::new("scc", 12.5, 4);
+        let sized = Measurement { items: Some(500), ..Measurement::new("std", 3.0, 1) };
+        let results = Results::new(&serde_json::json!({ "prefill": 1000 }), &[measurement, sized])
+            .mark_maps(|name| Some((name.to_string(), KeyType::U64)));
+
+        let dir = std::env::temp_dir().join(format!("map-bench-results-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        results.write(&dir, "test.svg").unwrap();
+
+        let loaded = Results::load(&dir.join("test.json")).unwrap();
+        assert_eq!(loaded.config["prefill"], 1000);
+        assert_eq!(loaded.records[0].adapter.as_deref(), Some("scc"));
+        assert_eq!(loaded.records[0].measurement().latency, 12.5);
+        assert_eq!(loaded.records[0].measurement().x(), 4);
+        assert_eq!(loaded.records[1].measurement().x(), 500);
+
+        let csv = std::fs::read_to_string(dir.join("test.csv")).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,427 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use thousands::Separable;
+
+use crate::affinity::Pinning;
+use crate::map_adapters::registry;
+use crate::perf::TrialConfig;
+use crate::perf_map::Mix;
+use crate::perf_mem;
+use crate::plot::{Chart, Metric};
+
+/// A shared map benchmark described in a TOML or JSON file, e.g.
+///
+/// ```toml
+/// mix = { read = 99, insert = 1 }
+/// key_type = "u64"
+/// prefill = 1_000_000
+/// total_ops = 40_000_000
+/// threads = [1, 2, 4, 8, 16]
+/// trials = 5
+/// maps = ["scc", "bfix", "nop"]
+/// chart = "latency99-1000000.svg"
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    /// Chart caption, derived from the mix and sizes if omitted.
+    #[serde(default)]
+    pub title: Option<String>,
+    pub mix: Mix,
+    #[serde(default)]
+    pub key_type: KeyType,
+    #[serde(default)]
+    pub key_distribution: KeyDistribution,
+    pub prefill: usize,
+    pub total_ops: usize,
+    /// Thread counts to run, from 1 up to the number of cpus if empty.
+    #[serde(default)]
+    pub threads: Vec<usize>,
+    pub maps: Vec<String>,
+    #[serde(default)]
+    pub pinning: Pinning,
+    /// Time one operation in this many for the latency percentiles.
+    #[serde(default = "default_sample_every")]
+    pub sample_every: usize,
+    /// Untimed runs before each point.
+    #[serde(default)]
+    pub warmup: usize,
+    /// Runs per point, charted as the mean with a 95% confidence band.
+    #[serde(default = "default_trials")]
+    pub trials: usize,
+    /// Leave out trials more than 1.5 interquartile ranges from the others.
+    #[serde(default)]
+    pub reject_outliers: bool,
+    /// Sample progress every this many milliseconds and chart throughput
+    /// over time for each thread count.
+    #[serde(default)]
+    pub timeline_ms: Option<u64>,
+    /// Target operations per second, all threads together. When set each map
+    /// is run open loop at every rate and the latency is charted against the
+    /// throughput it achieved, per thread count.
+    #[serde(default)]
+    pub rates: Vec<f64>,
+    /// Check the outcome of every operation and the contents of each map
+    /// after its run, failing if it lost or invented keys.
+    #[serde(default)]
+    pub verify: bool,
+    /// Metrics to chart, each to its own file.
+    #[serde(default = "default_metrics")]
+    pub metrics: Vec<Metric>,
+    /// Charts to draw of each metric, a line chart if omitted.
+    #[serde(default = "default_charts")]
+    pub charts: Vec<Chart>,
+    /// File name of the chart, relative to the output directory.
+    pub chart: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyType {
+    #[default]
+    U64,
+    String,
+}
+
+/// How existing keys are picked for reads, updates and removes, e.g.
+/// `key_distribution = { zipf = 0.99 }` or
+/// `key_distribution = { hotspot = { ops = 90, keys = 10 } }`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyDistribution {
+    /// Every key is as likely.
+    #[default]
+    Uniform,
+    /// The nth key in insertion order is picked in proportion to 1 / n^theta,
+    /// theta between 0 and 1. 0.99 is the usual choice for a skewed cache.
+    Zipf(f64),
+    /// `ops` percent of the operations go to the first `keys` percent of the
+    /// keys, the rest to the others.
+    Hotspot { ops: u32, keys: u32 },
+    /// The keys the thread inserted last, falling off as Zipf with theta
+    /// 0.99 back into the prefilled keys.
+    Latest,
+    /// Each thread walks the keys in insertion order.
+    Sequential,
+}
+
+impl KeyDistribution {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            KeyDistribution::Zipf(theta) if !(theta > 0.0 && theta < 1.0) => {
+                Err(format!("zipf theta must be between 0 and 1, not {theta}"))
+            }
+            KeyDistribution::Hotspot { ops, keys } if ops > 100 || keys == 0 || keys > 100 => {
+                Err(format!("hotspot needs ops up to 100% on keys of 1 to 100%, not {ops}% on {keys}%"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Short name for chart and file names, e.g. `zipf0.99` or `hotspot90-10`.
+    pub fn name(&self) -> String {
+        match self {
+            KeyDistribution::Uniform => "uniform".to_string(),
+            KeyDistribution::Zipf(theta) => format!("zipf{theta}"),
+            KeyDistribution::Hotspot { ops, keys } => format!("hotspot{ops}-{keys}"),
+            KeyDistribution::Latest => "latest".to_string(),
+            KeyDistribution::Sequential => "sequential".to_string(),
+        }
+    }
+
+    /// Added to chart file names so runs with other keys don't overwrite the
+    /// uniform ones, empty for uniform.
+    pub fn file_suffix(&self) -> String {
+        match self {
+            KeyDistribution::Uniform => String::new(),
+            other => format!("-{}", other.name()),
+        }
+    }
+}
+
+/// Parses the command line form: `uniform`, `zipf` or `zipf:0.8`,
+/// `hotspot:90:10`, `latest` or `sequential`.
+impl std::str::FromStr for KeyDistribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn number<T: std::str::FromStr>(part: &str, s: &str) -> Result<T, String> {
+            part.parse().map_err(|_| format!("invalid number '{part}' in key distribution '{s}'"))
+        }
+
+        let parts: Vec<&str> = s.split(':').collect();
+        let distribution = match parts.as_slice() {
+            ["uniform"] => KeyDistribution::Uniform,
+            ["zipf"] => KeyDistribution::Zipf(0.99),
+            ["zipf", theta] => KeyDistribution::Zipf(number(theta, s)?),
+            ["hotspot", ops, keys] => KeyDistribution::Hotspot {
+                ops: number(ops, s)?,
+                keys: number(keys, s)?,
+            },
+            ["latest"] => KeyDistribution::Latest,
+            ["sequential"] => KeyDistribution::Sequential,
+            _ => {
+                return Err(format!(
+                    "unknown key distribution '{s}', expected uniform, zipf[:theta], hotspot:ops%:keys%, latest or sequential"
+                ))
+            }
+        };
+        distribution.validate()?;
+        Ok(distribution)
+    }
+}
+
+fn default_sample_every() -> usize {
+    16
+}
+
+fn default_trials() -> usize {
+    1
+}
+
+fn default_metrics() -> Vec<Metric> {
+    vec![Metric::Latency]
+}
+
+fn default_charts() -> Vec<Chart> {
+    vec![Chart::Lines]
+}
+
+impl Scenario {
+    /// Reads a scenario, as JSON if the file ends in `.json` and TOML otherwise.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read scenario {}: {}", path.display(), e))?;
+
+        let scenario: Scenario = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&text).map_err(|e| e.to_string())
+        } else {
+            toml::from_str(&text).map_err(|e| e.to_string())
+        }
+        .map_err(|e| format!("Invalid scenario {}: {}", path.display(), e))?;
+
+        scenario
+            .validate()
+            .map_err(|e| format!("Invalid scenario {}: {}", path.display(), e))?;
+        Ok(scenario)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let total: usize = self.mix.percents().iter().map(|(_, percent)| percent).sum();
+        if total != 100 {
+            return Err(format!("mix adds up to {total}%, not 100%"));
+        }
+        if self.total_ops == 0 {
+            return Err("total_ops must be more than zero".to_string());
+        }
+        if self.trials == 0 {
+            return Err("trials must be more than zero".to_string());
+        }
+        if self.threads.contains(&0) {
+            return Err("thread counts must be more than zero".to_string());
+        }
+        if self.rates.iter().any(|&rate| !rate.is_finite() || rate <= 0.0) {
+            return Err("rates must be finite and more than zero".to_string());
+        }
+        if self.maps.is_empty() {
+            return Err("no maps listed".to_string());
+        }
+        self.key_distribution.validate()?;
+        for adapter in registry::find_all(&self.maps)? {
+            adapter.check_mix(&self.mix)?;
+        }
+        Ok(())
+    }
+
+    pub fn thread_counts(&self) -> Vec<usize> {
+        if self.threads.is_empty() {
+            (1..=perf_mem::get_num_cpus()).collect()
+        } else {
+            self.threads.clone()
+        }
+    }
+
+    pub fn expected_inserts(&self) -> usize {
+        self.total_ops * self.mix.insert / 100
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.prefill + self.expected_inserts()
+    }
+
+    pub fn total_keys(&self) -> usize {
+        self.prefill + self.expected_inserts() + 1000 // 1000 needed for some rounding error?
+    }
+
+    pub fn trial_config(&self) -> TrialConfig {
+        TrialConfig {
+            warmup: self.warmup,
+            trials: self.trials,
+            reject_outliers: self.reject_outliers,
+        }
+    }
+
+    /// Identifies the workload, for matching external series to it. The mix
+    /// and prefill, e.g. `read99-insert1-1000000`, with `-string` added for
+    /// string keys and the key distribution if it isn't uniform.
+    pub fn workload(&self) -> String {
+        let m = &self.mix;
+        let mut parts: Vec<String> = [
+            ("read", m.read),
+            ("insert", m.insert),
+            ("remove", m.remove),
+            ("update", m.update),
+            ("upsert", m.upsert),
+            ("getorinsert", m.get_or_insert),
+            ("compute", m.compute),
+        ]
+        .iter()
+        .filter(|(_, percent)| *percent > 0)
+        .map(|(op, percent)| format!("{op}{percent}"))
+        .collect();
+        parts.push(self.prefill.to_string());
+        if self.key_type == KeyType::String {
+            parts.push("string".to_string());
+        }
+        if self.key_distribution != KeyDistribution::Uniform {
+            parts.push(self.key_distribution.name());
+        }
+        parts.join("-")
+    }
+
+    pub fn title(&self) -> String {
+        match &self.title {
+            Some(title) => title.clone(),
+            None => {
+                let keys = match self.key_distribution {
+                    KeyDistribution::Uniform => String::new(),
+                    other => format!("   keys = {}", other.name()),
+                };
+                format!(
+                    "Average latency (read = {}%   items = {}+{}{})",
+                    self.mix.read,
+                    self.prefill.separate_with_commas(),
+                    self.expected_inserts().separate_with_commas(),
+                    keys
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toml_and_json_agree() {
+        let from_toml: Scenario = toml::from_str(
+            r#"
+            mix = { read = 90, insert = 5, remove = 5 }
+            key_type = "string"
+            prefill = 1000
+            total_ops = 100000
+            threads = [1, 2]
+            maps = ["scc", "nop"]
+            pinning = "numa"
+            chart = "test.svg"
+            "#,
+        )
+        .unwrap();
+
+        let from_json: Scenario = serde_json::from_str(
+            r#"{
+                "mix": { "read": 90, "insert": 5, "remove": 5 },
+                "key_type": "string",
+                "prefill": 1000,
+                "total_ops": 100000,
+                "threads": [1, 2],
+                "maps": ["scc", "nop"],
+                "pinning": "numa",
+                "chart": "test.svg"
+            }"#,
+        )
+        .unwrap();
+
+        for s in [&from_toml, &from_json] {
+            assert!(s.validate().is_ok());
+            assert_eq!(s.mix.remove, 5);
+            assert_eq!(s.key_type, KeyType::String);
+            assert_eq!(s.key_distribution, KeyDistribution::Uniform);
+            assert_eq!(s.pinning, Pinning::NumaNode);
+            assert_eq!(s.thread_counts(), vec![1, 2]);
+            assert_eq!(s.capacity(), 6000);
+            assert_eq!(s.workload(), "read90-insert5-remove5-1000-string");
+        }
+    }
+
+    #[test]
+    fn test_key_distributions() {
+        let parse = |text: &str| toml::from_str::<Scenario>(&format!(
+            "mix = {{ read = 100 }}\nprefill = 1000\ntotal_ops = 1000\nmaps = [\"scc\"]\nchart = \"test.svg\"\n{}",
+            text
+        ));
+        let hotspot = parse("key_distribution = { hotspot = { ops = 90, keys = 10 } }").unwrap();
+        assert_eq!(hotspot.key_distribution, KeyDistribution::Hotspot { ops: 90, keys: 10 });
+        assert_eq!(hotspot.workload(), "read100-1000-hotspot90-10");
+        let zipf = parse("key_distribution = { zipf = 0.99 }").unwrap();
+        assert_eq!(zipf.key_distribution, "zipf".parse().unwrap());
+        assert!(parse("key_distribution = { zipf = 1.5 }").unwrap().validate().is_err());
+        assert_eq!(parse("key_distribution = \"latest\"").unwrap().key_distribution, KeyDistribution::Latest);
+
+        assert_eq!("hotspot:80:20".parse(), Ok(KeyDistribution::Hotspot { ops: 80, keys: 20 }));
+        assert!("hotspot:80".parse::<KeyDistribution>().is_err());
+        assert!("hotspot:90.5:10".parse::<KeyDistribution>().is_err());
+        assert!("hotspot:-5:10".parse::<KeyDistribution>().is_err());
+        assert!("zipf:0".parse::<KeyDistribution>().is_err());
+    }
+
+    #[test]
+    fn test_mix_must_add_up() {
+        let scenario: Scenario = toml::from_str(
+            r#"
+            mix = { read = 90 }
+            prefill = 1000
+            total_ops = 100000
+            maps = ["scc"]
+            chart = "test.svg"
+            "#,
+        )
+        .unwrap();
+
+        assert!(scenario.validate().is_err());
+    }
+
+    #[test]
+    fn test_rates_must_be_positive() {
+        let parse = |rates: &str| toml::from_str::<Scenario>(&format!(
+            "mix = {{ read = 100 }}\nprefill = 1000\ntotal_ops = 1000\nmaps = [\"scc\"]\nrates = {}\nchart = \"test.svg\"",
+            rates
+        ));
+        assert!(parse("[1000.0]").unwrap().validate().is_ok());
+        for rates in ["[0.0]", "[-5.0]", "[nan]", "[inf]"] {
+            assert!(parse(rates).unwrap().validate().is_err(), "{rates}");
+        }
+    }
+
+    #[test]
+    fn test_maps_must_have_write_ops() {
+        let parse = |op: &str, maps: &str| toml::from_str::<Scenario>(&format!(
+            "mix = {{ read = 90, {} = 10 }}\nprefill = 1000\ntotal_ops = 1000\nmaps = {}\nchart = \"test.svg\"",
+            op, maps
+        ));
+        assert!(parse("compute", "[\"scc\", \"evmap\"]").unwrap().validate().is_ok());
+        assert!(parse("upsert", "[\"scc\", \"bfix\"]").unwrap().validate().is_ok());
+        let err = parse("compute", "[\"scc\", \"bfix\"]").unwrap().validate().unwrap_err();
+        assert_eq!(err, "map 'bfix' has no atomic compute");
+    }
+
+    #[test]
+    fn test_example_scenarios_load() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            Scenario::load(&path).unwrap();
+        }
+    }
+}
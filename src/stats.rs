@@ -0,0 +1,164 @@
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+const BOOTSTRAP_RESAMPLES: usize = 2000;
+const CONFIDENCE: f64 = 0.95;
+
+/// Summary statistics of a value measured over repeated trials.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    pub mean: f64,
+    pub median: f64,
+    pub stddev: f64,
+    /// 95% bootstrap confidence interval of the mean.
+    pub ci_low: f64,
+    pub ci_high: f64,
+}
+
+impl Summary {
+    pub fn new(values: &[f64]) -> Self {
+        assert!(!values.is_empty(), "no values to summarize");
+
+        let mean = mean(values);
+        let (ci_low, ci_high) = bootstrap_ci(values);
+        Summary {
+            mean,
+            median: median(values),
+            stddev: stddev(values, mean),
+            ci_low,
+            ci_high,
+        }
+    }
+}
+
+/// Two-sided 97.5% quantiles of Student's t distribution for 1 to 30
+/// degrees of freedom. Past 30 the normal quantile is close enough.
+const T_975: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160, 2.145, 2.131,
+    2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056, 2.052, 2.048, 2.045, 2.042,
+];
+
+/// Welch's t-test of whether two means differ at the 95% level, given the
+/// summaries of `n_a` and `n_b` trials. `None` if either side has fewer than
+/// two trials, so its variance is unknown.
+pub fn means_differ(a: &Summary, n_a: usize, b: &Summary, n_b: usize) -> Option<bool> {
+    if n_a < 2 || n_b < 2 {
+        return None;
+    }
+
+    let (va, vb) = (a.stddev * a.stddev / n_a as f64, b.stddev * b.stddev / n_b as f64);
+    if va + vb == 0.0 {
+        return Some(a.mean != b.mean);
+    }
+
+    let t = (a.mean - b.mean).abs() / (va + vb).sqrt();
+    let dof = (va + vb).powi(2) / (va * va / (n_a - 1) as f64 + vb * vb / (n_b - 1) as f64);
+    // Rounding the degrees of freedom down keeps the test conservative
+    let critical = T_975.get((dof.floor() as usize).max(1) - 1).copied().unwrap_or(1.960);
+    Some(t > critical)
+}
+
+/// Indexes of the values inside Tukey's fences, 1.5 interquartile ranges
+/// beyond the quartiles, leaving out the outliers. With fewer than four
+/// values the quartiles mean little, so nothing is rejected.
+pub fn reject_outliers(values: &[f64]) -> Vec<usize> {
+    if values.len() < 4 {
+        return (0..values.len()).collect();
+    }
+
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let q1 = quantile(&sorted, 0.25);
+    let q3 = quantile(&sorted, 0.75);
+    let fence = 1.5 * (q3 - q1);
+
+    (0..values.len())
+        .filter(|&i| values[i] >= q1 - fence && values[i] <= q3 + fence)
+        .collect()
+}
+
+pub fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+pub fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    quantile(&sorted, 0.5)
+}
+
+/// Sample standard deviation, zero for a single value.
+fn stddev(values: &[f64], mean: f64) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let sum_sq: f64 = values.iter().map(|v| (v - mean) * (v - mean)).sum();
+    (sum_sq / (values.len() - 1) as f64).sqrt()
+}
+
+/// Linear interpolation between the closest ranks of sorted values.
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let pos = q * (sorted.len() - 1) as f64;
+    let low = pos.floor() as usize;
+    let high = pos.ceil() as usize;
+    sorted[low] + (sorted[high] - sorted[low]) * (pos - low as f64)
+}
+
+/// Percentile bootstrap of the mean. The seed is fixed so the same trials
+/// always give the same interval.
+fn bootstrap_ci(values: &[f64]) -> (f64, f64) {
+    if values.len() < 2 {
+        return (values[0], values[0]);
+    }
+
+    let mut rng = SmallRng::seed_from_u64(0x5eed);
+    let mut means: Vec<f64> = (0..BOOTSTRAP_RESAMPLES)
+        .map(|_| {
+            let sum: f64 = (0..values.len())
+                .map(|_| values[rng.gen_range(0..values.len())])
+                .sum();
+            sum / values.len() as f64
+        })
+        .collect();
+    means.sort_by(f64::total_cmp);
+
+    let tail = (1.0 - CONFIDENCE) / 2.0;
+    (quantile(&means, tail), quantile(&means, 1.0 - tail))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let values = [10.0, 12.0, 11.0, 13.0, 9.0];
+        let s = Summary::new(&values);
+
+        assert_eq!(s.mean, 11.0);
+        assert_eq!(s.median, 11.0);
+        assert!((s.stddev - 2.5f64.sqrt()).abs() < 1e-9);
+        assert!(s.ci_low < s.mean && s.mean < s.ci_high);
+        assert!(s.ci_low >= 9.0 && s.ci_high <= 13.0);
+    }
+
+    #[test]
+    fn test_means_differ() {
+        let a = Summary::new(&[10.0, 10.2, 9.8, 10.1, 9.9]);
+        let b = Summary::new(&[10.1, 9.9, 10.3, 9.7, 10.0]);
+        let c = Summary::new(&[12.0, 12.2, 11.8, 12.1, 11.9]);
+
+        assert_eq!(means_differ(&a, 5, &b, 5), Some(false));
+        assert_eq!(means_differ(&a, 5, &c, 5), Some(true));
+        assert_eq!(means_differ(&a, 1, &c, 5), None);
+    }
+
+    #[test]
+    fn test_reject_outliers() {
+        let kept = reject_outliers(&[10.0, 11.0, 40.0, 10.5, 9.5, 10.2]);
+        assert_eq!(kept, vec![0, 1, 3, 4, 5]);
+
+        assert_eq!(reject_outliers(&[1.0, 100.0]), vec![0, 1]);
+    }
+}
@@ -0,0 +1,375 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::perf_info::{CacheInfo, MachineInfo, NumaNodeInfo, PackageInfo, PhysicalCore};
+use crate::perf_mem::{distribute_numa_cores, CoreId, CoreInfo};
+
+/// Number of logical processors per group, matching Windows processor groups
+/// so `CoreId` masks mean the same thing on both platforms.
+const GROUP_SIZE: usize = 64;
+
+/// Reader for the Linux `/sys/devices/system` topology files.
+///
+/// The root is configurable so the parsing can be pointed at a fixture tree.
+pub struct SysFs {
+    root: PathBuf,
+}
+
+impl Default for SysFs {
+    fn default() -> Self {
+        Self::new("/sys")
+    }
+}
+
+impl SysFs {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn read(&self, rel: &str) -> Result<String, String> {
+        let path = self.root.join(rel);
+        fs::read_to_string(&path)
+            .map(|s| s.trim().to_string())
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+    }
+
+    pub fn read_cpu_file(&self, cpu: usize, rel: &str) -> Result<String, String> {
+        self.read(&format!("devices/system/cpu/cpu{cpu}/{rel}"))
+    }
+
+    pub fn read_node_file(&self, node: u32, rel: &str) -> Result<String, String> {
+        self.read(&format!("devices/system/node/node{node}/{rel}"))
+    }
+
+    pub fn online_cpus(&self) -> Result<Vec<usize>, String> {
+        parse_cpu_list(&self.read("devices/system/cpu/online")?)
+    }
+
+    /// Returns the numa nodes and the cpus that belong to them. Kernels built
+    /// without NUMA support have no node directory, so everything is node 0.
+    pub fn numa_nodes(&self) -> Result<BTreeMap<u32, Vec<usize>>, String> {
+        let node_dir = self.root.join("devices/system/node");
+        let mut nodes = BTreeMap::new();
+
+        if let Ok(entries) = fs::read_dir(&node_dir) {
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                let Some(num) = name.to_str().and_then(|n| n.strip_prefix("node")) else {
+                    continue;
+                };
+                let Ok(node) = num.parse::<u32>() else {
+                    continue;
+                };
+                let cpus = parse_cpu_list(&self.read_node_file(node, "cpulist")?)?;
+                nodes.insert(node, cpus);
+            }
+        }
+
+        if nodes.is_empty() {
+            nodes.insert(0, self.online_cpus()?);
+        }
+
+        Ok(nodes)
+    }
+
+    pub fn thread_siblings(&self, cpu: usize) -> Result<Vec<usize>, String> {
+        match self.read_cpu_file(cpu, "topology/thread_siblings_list") {
+            Ok(list) => parse_cpu_list(&list),
+            Err(_) => Ok(vec![cpu]),
+        }
+    }
+
+    pub fn core_info(&self) -> Result<CoreInfo, String> {
+        let online = self.online_cpus()?;
+        let mut core_infos = Vec::new();
+        let mut numa_nodes = Vec::new();
+
+        for (node, cpus) in self.numa_nodes()? {
+            let cpus: Vec<usize> = cpus.into_iter().filter(|c| online.contains(c)).collect();
+            if cpus.is_empty() {
+                // memory only node
+                continue;
+            }
+
+            numa_nodes.push(node);
+
+            for &cpu in &cpus {
+                let processor_group = (cpu / GROUP_SIZE) as u16;
+                let siblings = self.thread_siblings(cpu)?;
+
+                core_infos.push(CoreId {
+                    processor_group,
+                    core_mask: 1 << (cpu % GROUP_SIZE),
+                    numa_mask: group_mask(&cpus, processor_group),
+                    smt_mask: group_mask(&siblings, processor_group),
+                    numa_node_num: node,
+                });
+            }
+        }
+
+        if core_infos.is_empty() {
+            return Err(format!(
+                "No online cpus found under {}",
+                self.root.display()
+            ));
+        }
+
+        Ok(CoreInfo {
+            ids: distribute_numa_cores(core_infos),
+            numa_nodes,
+        })
+    }
+}
+
+impl SysFs {
+    /// Collects packages, cores, numa nodes and caches. `cpuinfo` is the
+    /// contents of `/proc/cpuinfo`, which is only used for the model name.
+    pub fn machine_info(&self, cpuinfo: &str) -> Result<MachineInfo, String> {
+        let online = self.online_cpus()?;
+
+        let cpu_model = cpuinfo
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim() == "model name")
+            .map(|(_, value)| value.trim().to_string())
+            .unwrap_or_default();
+
+        // package id -> core id -> cpus
+        let mut packages = BTreeMap::<u32, BTreeMap<u32, Vec<usize>>>::new();
+        let mut caches = Vec::<CacheInfo>::new();
+
+        for &cpu in &online {
+            let read_id = |rel: &str| {
+                self.read_cpu_file(cpu, rel)
+                    .ok()
+                    .and_then(|id| id.parse::<i64>().ok())
+                    .map_or(0, |id| id.max(0) as u32)
+            };
+
+            packages
+                .entry(read_id("topology/physical_package_id"))
+                .or_default()
+                .entry(read_id("topology/core_id"))
+                .or_default()
+                .push(cpu);
+
+            for index in 0.. {
+                let cache_file = |name: &str| self.read_cpu_file(cpu, &format!("cache/index{index}/{name}"));
+                let Ok(level) = cache_file("level") else {
+                    break;
+                };
+
+                let cache = CacheInfo {
+                    level: level.parse().unwrap_or(0),
+                    kind: cache_file("type").unwrap_or_default(),
+                    size_bytes: cache_file("size").map_or(0, |s| parse_size(&s)),
+                    line_size: cache_file("coherency_line_size")
+                        .ok()
+                        .and_then(|s| s.parse().ok())
+                        .unwrap_or(0),
+                    ways: cache_file("ways_of_associativity")
+                        .ok()
+                        .and_then(|s| s.parse().ok()),
+                    shared_cpus: cache_file("shared_cpu_list")
+                        .map_or(Ok(vec![cpu]), |s| parse_cpu_list(&s))?,
+                };
+
+                // every cpu sharing the cache reports it
+                let known = caches.iter().any(|c| {
+                    c.level == cache.level && c.kind == cache.kind && c.shared_cpus == cache.shared_cpus
+                });
+                if !known {
+                    caches.push(cache);
+                }
+            }
+        }
+
+        let mut numa_nodes = Vec::new();
+        for (id, cpus) in self.numa_nodes()? {
+            let memory_bytes = self.read_node_file(id, "meminfo").ok().and_then(|meminfo| {
+                meminfo
+                    .lines()
+                    .find(|line| line.contains("MemTotal:"))
+                    .and_then(|line| line.split_once("MemTotal:"))
+                    .map(|(_, size)| parse_size(size))
+            });
+
+            let distances = self
+                .read_node_file(id, "distance")
+                .map(|d| d.split_whitespace().filter_map(|v| v.parse().ok()).collect())
+                .unwrap_or_default();
+
+            numa_nodes.push(NumaNodeInfo {
+                id,
+                cpus,
+                memory_bytes,
+                distances,
+            });
+        }
+
+        Ok(MachineInfo {
+            os: std::env::consts::OS.to_string(),
+            cpu_model,
+            logical_cpus: online.len(),
+            packages: packages
+                .into_iter()
+                .map(|(id, cores)| PackageInfo {
+                    id,
+                    cores: cores
+                        .into_iter()
+                        .map(|(id, cpus)| PhysicalCore { id, cpus })
+                        .collect(),
+                })
+                .collect(),
+            numa_nodes,
+            caches,
+        })
+    }
+}
+
+// sizes in sysfs are written as "48K", "2048K" or "5209848 kB"
+fn parse_size(size: &str) -> u64 {
+    let size = size.trim();
+    let digits = size.find(|c: char| !c.is_ascii_digit()).unwrap_or(size.len());
+    let value: u64 = size[..digits].parse().unwrap_or(0);
+
+    match size[digits..].trim().chars().next() {
+        Some('K' | 'k') => value * 1024,
+        Some('M' | 'm') => value * 1024 * 1024,
+        Some('G' | 'g') => value * 1024 * 1024 * 1024,
+        _ => value,
+    }
+}
+
+// mask of the cpus in the list that fall into the given processor group
+fn group_mask(cpus: &[usize], processor_group: u16) -> usize {
+    cpus.iter()
+        .filter(|&&c| c / GROUP_SIZE == processor_group as usize)
+        .fold(0, |mask, &c| mask | 1 << (c % GROUP_SIZE))
+}
+
+/// Parses the kernel cpu list format, e.g. "0-3,8,10-11".
+pub fn parse_cpu_list(list: &str) -> Result<Vec<usize>, String> {
+    let mut cpus = Vec::new();
+
+    for part in list.trim().split(',').filter(|p| !p.is_empty()) {
+        let parse = |s: &str| {
+            s.trim()
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid cpu list '{list}'"))
+        };
+
+        match part.split_once('-') {
+            Some((first, last)) => cpus.extend(parse(first)?..=parse(last)?),
+            None => cpus.push(parse(part)?),
+        }
+    }
+
+    Ok(cpus)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two nodes, four cores per node, two threads per core.
+    fn write_fixture(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("map-bench-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        let write = |rel: &str, text: &str| {
+            let path = root.join(rel);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        };
+
+        write("devices/system/cpu/online", "0-15\n");
+        write("devices/system/node/node0/cpulist", "0-3,8-11\n");
+        write("devices/system/node/node1/cpulist", "4-7,12-15\n");
+        write("devices/system/node/node2/cpulist", "\n");
+
+        write("devices/system/node/node0/distance", "10 21 17\n");
+        write("devices/system/node/node0/meminfo", "Node 0 MemTotal:  1024 kB\nNode 0 MemFree: 512 kB\n");
+
+        for cpu in 0..16 {
+            let first = cpu % 8;
+            let topology = format!("devices/system/cpu/cpu{cpu}/topology");
+            write(&format!("{topology}/thread_siblings_list"), &format!("{},{}\n", first, first + 8));
+            write(&format!("{topology}/physical_package_id"), &format!("{}\n", first / 4));
+            write(&format!("{topology}/core_id"), &format!("{}\n", first % 4));
+
+            let cache = format!("devices/system/cpu/cpu{cpu}/cache/index0");
+            write(&format!("{cache}/level"), "3\n");
+            write(&format!("{cache}/type"), "Unified\n");
+            write(&format!("{cache}/size"), "16384K\n");
+            write(&format!("{cache}/coherency_line_size"), "64\n");
+            write(&format!("{cache}/shared_cpu_list"), if first < 4 { "0-3,8-11" } else { "4-7,12-15" });
+        }
+
+        root
+    }
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0-3,8,10-11\n").unwrap(), vec![0, 1, 2, 3, 8, 10, 11]);
+        assert_eq!(parse_cpu_list("").unwrap(), Vec::<usize>::new());
+        assert!(parse_cpu_list("0-x").is_err());
+    }
+
+    #[test]
+    fn test_core_info_from_fixture() {
+        let root = write_fixture("sysfs");
+        let info = SysFs::new(&root).core_info().unwrap();
+
+        assert_eq!(info.numa_nodes, vec![0, 1]);
+        assert_eq!(info.ids.len(), 16);
+
+        let cpu9 = info.ids.iter().find(|c| c.core_mask == 1 << 9).unwrap();
+        assert_eq!(cpu9.numa_node_num, 0);
+        assert_eq!(cpu9.numa_mask, 0x0F0F);
+        assert_eq!(cpu9.smt_mask, (1 << 1) | (1 << 9));
+
+        // cores should alternate between the nodes
+        assert_ne!(info.ids[0].numa_node_num, info.ids[1].numa_node_num);
+        // and use every physical core before its SMT siblings
+        assert!(info.ids[..8].iter().all(|c| c.cpu_index() < 8));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_core_info_sparse_nodes() {
+        let root = write_fixture("sysfs-sparse");
+        let node_dir = root.join("devices/system/node");
+        fs::rename(node_dir.join("node1"), node_dir.join("node3")).unwrap();
+
+        let info = SysFs::new(&root).core_info().unwrap();
+        assert_eq!(info.numa_nodes, vec![0, 3]);
+        assert_eq!(info.numa_node_index(3), 1);
+        assert_eq!(info.next_numa_node(3), 0);
+        assert_eq!(info.next_numa_node(0), 3);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_machine_info_from_fixture() {
+        let root = write_fixture("machine");
+        let cpuinfo = "processor\t: 0\nmodel name\t: Test CPU @ 2.00GHz\n";
+        let info = SysFs::new(&root).machine_info(cpuinfo).unwrap();
+
+        assert_eq!(info.cpu_model, "Test CPU @ 2.00GHz");
+        assert_eq!(info.logical_cpus, 16);
+        assert_eq!(info.packages.len(), 2);
+        assert_eq!(info.packages[0].cores.len(), 4);
+        assert_eq!(info.packages[0].cores[1].cpus, vec![1, 9]);
+        assert_eq!(info.numa_nodes.len(), 3);
+        assert_eq!(info.numa_nodes[0].distances, vec![10, 21, 17]);
+        assert_eq!(info.numa_nodes[0].memory_bytes, Some(1024 * 1024));
+        assert_eq!(info.caches.len(), 2);
+        assert_eq!(info.caches[0].size_bytes, 16 * 1024 * 1024);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often the benchmark threads publish their op count. Publishing every
+/// op would make the sampled threads fight over cache lines.
+pub const PUBLISH_EVERY: usize = 256;
+
+/// A completed-op counter on its own cache line, written by one thread and
+/// read by the sampler.
+#[repr(align(64))]
+#[derive(Default)]
+pub struct ProgressCounter(AtomicU64);
+
+impl ProgressCounter {
+    #[inline]
+    pub fn publish(&self, ops: usize) {
+        self.0.store(ops as u64, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The op counts of every thread at one moment.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    /// Seconds since the threads were released.
+    pub secs: f64,
+    pub thread_ops: Vec<u64>,
+}
+
+/// The progress of one run, sampled at a fixed interval.
+#[derive(Debug, Clone)]
+pub struct Timeline {
+    pub name: String,
+    pub thread_count: u64,
+    pub samples: Vec<Sample>,
+}
+
+impl Timeline {
+    /// Operations per second of all threads over each interval, at the end of
+    /// the interval.
+    pub fn throughput(&self) -> Vec<(f64, f64)> {
+        self.samples
+            .windows(2)
+            .filter(|w| w[1].secs > w[0].secs)
+            .map(|w| {
+                let ops: u64 = w[1].thread_ops.iter().sum::<u64>() - w[0].thread_ops.iter().sum::<u64>();
+                (w[1].secs, ops as f64 / (w[1].secs - w[0].secs))
+            })
+            .collect()
+    }
+}
+
+/// Collects the timelines of the runs in a suite. Runs given a recorder start
+/// a sampler thread alongside the benchmark threads.
+#[derive(Debug)]
+pub struct TimelineRecorder {
+    pub interval: Duration,
+    timelines: Mutex<Vec<Timeline>>,
+}
+
+impl TimelineRecorder {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            timelines: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn push(&self, timeline: Timeline) {
+        self.timelines.lock().unwrap().push(timeline);
+    }
+
+    /// The timelines recorded so far, keeping only the latest of repeated
+    /// trials of the same map and thread count.
+    pub fn latest(&self) -> Vec<Timeline> {
+        let timelines = self.timelines.lock().unwrap();
+        let mut latest: Vec<Timeline> = Vec::new();
+        for timeline in timelines.iter() {
+            latest.retain(|t| t.name != timeline.name || t.thread_count != timeline.thread_count);
+            latest.push(timeline.clone());
+        }
+        latest
+    }
+}
+
+/// A thread that snapshots the progress counters until it is finished.
+pub struct Sampler {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Vec<Sample>>,
+}
+
+impl Sampler {
+    /// Starts sampling, counting time from `start`.
+    pub fn start(counters: Arc<Vec<ProgressCounter>>, interval: Duration, start: Instant) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_clone = stop.clone();
+
+        let handle = thread::spawn(move || {
+            let snapshot = || Sample {
+                secs: start.elapsed().as_secs_f64(),
+                thread_ops: counters.iter().map(|c| c.get()).collect(),
+            };
+
+            let mut samples = vec![snapshot()];
+            let mut next = start + interval;
+            while !stop_clone.load(Ordering::Relaxed) {
+                // Sleep to a deadline rather than for the interval, so the
+                // samples don't drift
+                if let Some(wait) = next.checked_duration_since(Instant::now()) {
+                    thread::sleep(wait);
+                }
+                samples.push(snapshot());
+                next += interval;
+            }
+            samples.push(snapshot());
+            samples
+        });
+
+        Self { stop, handle }
+    }
+
+    pub fn finish(self) -> Vec<Sample> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.join().expect("sampler thread panicked")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throughput_from_samples() {
+        let timeline = Timeline {
+            name: "test".to_string(),
+            thread_count: 2,
+            samples: vec![
+                Sample { secs: 0.0, thread_ops: vec![0, 0] },
+                Sample { secs: 0.5, thread_ops: vec![100, 150] },
+                Sample { secs: 1.0, thread_ops: vec![100, 150] },
+                Sample { secs: 1.0, thread_ops: vec![100, 150] },
+            ],
+        };
+
+        assert_eq!(timeline.throughput(), vec![(0.5, 500.0), (1.0, 0.0)]);
+    }
+}